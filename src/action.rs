@@ -0,0 +1,190 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Deserializer};
+use x11rb::errors::ReplyOrIdError;
+
+use crate::config::spawn_program;
+use crate::keybind::{make_action, KeyBindAction};
+use crate::wm::{XConnection, WM};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Next,
+    Prev
+}
+
+impl Direction {
+    pub fn delta(self) -> i32 {
+        match self {
+            Direction::Next => 1,
+            Direction::Prev => -1
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "next" => Ok(Direction::Next),
+            "prev" => Ok(Direction::Prev),
+            _ => Err(format!("invalid direction '{}', expected next or prev", s))
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Next => write!(f, "next"),
+            Direction::Prev => write!(f, "prev")
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Layout(String),
+    NextLayout,
+    PrevLayout,
+    ApplyLayout,
+    StackInc,
+    StackDec,
+    KillFocused,
+    ToggleFullscreen,
+    Reload,
+    Mode(String),
+    Workspace(usize),
+    CycleWorkspace(Direction),
+    MoveToWorkspace(usize),
+    FocusMonitor(Direction),
+    MoveToMonitor(Direction),
+    MasterRatio(i32),
+    MasterCount(i32),
+    Spawn(String)
+}
+
+impl Action {
+    pub fn execute<C: XConnection>(&self, wm: &mut WM<C>) -> Result<(), ReplyOrIdError> {
+        match self {
+            Action::Layout(name) => wm.set_layout(name),
+            Action::NextLayout  => wm.cycle_layout(1),
+            Action::PrevLayout  => wm.cycle_layout(-1),
+            Action::ApplyLayout => wm.apply_layout(),
+            Action::StackInc    => wm.stack_inc(),
+            Action::StackDec    => wm.stack_dec(),
+            Action::KillFocused => wm.kill_focused(),
+            Action::ToggleFullscreen => wm.toggle_fullscreen(),
+            Action::Reload      => { wm.request_reload(); Ok(()) },
+            Action::Mode(name)  => { wm.set_key_mode(name)?; Ok(()) },
+            Action::Workspace(n)       => wm.switch_workspace(n - 1),
+            Action::CycleWorkspace(dir) => wm.cycle_workspace(dir.delta()),
+            Action::MoveToWorkspace(n) => wm.move_focused_to_workspace(n - 1),
+            Action::FocusMonitor(dir)  => wm.focus_monitor(dir.delta()),
+            Action::MoveToMonitor(dir) => wm.move_focused_to_monitor(dir.delta()),
+            Action::MasterRatio(percent) => wm.change_master_ratio(*percent as f32 / 100.0),
+            Action::MasterCount(delta)   => wm.change_master_count(*delta),
+            Action::Spawn(cmd)  => spawn_program(cmd)
+        }
+    }
+
+    pub fn into_key_bind_action<C: XConnection>(self) -> KeyBindAction<C> {
+        make_action(move |wm| self.execute(wm))
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (name, arg) = match s.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (s, "")
+        };
+        let action = match name {
+            "layout"       => Action::Layout(arg.to_string()),
+            "next_layout"  => Action::NextLayout,
+            "prev_layout"  => Action::PrevLayout,
+            "apply_layout" => Action::ApplyLayout,
+            "stack_inc"    => Action::StackInc,
+            "stack_dec"    => Action::StackDec,
+            "kill_focused" => Action::KillFocused,
+            "toggle_fullscreen" => Action::ToggleFullscreen,
+            "reload"       => Action::Reload,
+            "mode"         => Action::Mode(arg.to_string()),
+            "workspace"         => match arg.parse() {
+                Ok(dir) => Action::CycleWorkspace(dir),
+                Err(_)  => Action::Workspace(parse_workspace(arg)?)
+            },
+            "move_to_workspace" => Action::MoveToWorkspace(parse_workspace(arg)?),
+            "focus_monitor"     => Action::FocusMonitor(arg.parse()?),
+            "move_to_monitor"   => Action::MoveToMonitor(arg.parse()?),
+            "master_ratio"      => Action::MasterRatio(parse_delta(arg)?),
+            "master_count"      => Action::MasterCount(parse_delta(arg)?),
+            "spawn"        => Action::Spawn(arg.to_string()),
+            _ => return Err(format!("unknown action '{}'", name))
+        };
+        match &action {
+            Action::Layout(name) if name.is_empty() =>
+                Err("layout requires a layout name".to_string()),
+            Action::Spawn(cmd) if cmd.is_empty() =>
+                Err("spawn requires a program".to_string()),
+            Action::Mode(name) if name.is_empty() =>
+                Err("mode requires a mode name".to_string()),
+            Action::Layout(_) | Action::Spawn(_) | Action::Mode(_) |
+            Action::Workspace(_) | Action::CycleWorkspace(_) | Action::MoveToWorkspace(_) |
+            Action::FocusMonitor(_) | Action::MoveToMonitor(_) |
+            Action::MasterRatio(_) | Action::MasterCount(_) => Ok(action),
+            _ if !arg.is_empty() =>
+                Err(format!("action '{}' takes no argument", name)),
+            _ => Ok(action)
+        }
+    }
+}
+
+// workspaces are numbered from 1 like the keys used to reach them.
+fn parse_workspace(arg: &str) -> Result<usize, String> {
+    match arg.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("invalid workspace '{}', expected a number starting at 1", arg))
+    }
+}
+
+fn parse_delta(arg: &str) -> Result<i32, String> {
+    arg.trim_start_matches('+').parse()
+        .map_err(|_| format!("invalid amount '{}', expected something like +1 or -1", arg))
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Layout(name) => write!(f, "layout {}", name),
+            Action::NextLayout   => write!(f, "next_layout"),
+            Action::PrevLayout   => write!(f, "prev_layout"),
+            Action::ApplyLayout  => write!(f, "apply_layout"),
+            Action::StackInc     => write!(f, "stack_inc"),
+            Action::StackDec     => write!(f, "stack_dec"),
+            Action::KillFocused  => write!(f, "kill_focused"),
+            Action::ToggleFullscreen => write!(f, "toggle_fullscreen"),
+            Action::Reload       => write!(f, "reload"),
+            Action::Mode(name)   => write!(f, "mode {}", name),
+            Action::Workspace(n)       => write!(f, "workspace {}", n),
+            Action::CycleWorkspace(dir) => write!(f, "workspace {}", dir),
+            Action::MoveToWorkspace(n) => write!(f, "move_to_workspace {}", n),
+            Action::FocusMonitor(dir)  => write!(f, "focus_monitor {}", dir),
+            Action::MoveToMonitor(dir) => write!(f, "move_to_monitor {}", dir),
+            Action::MasterRatio(percent) => write!(f, "master_ratio {:+}", percent),
+            Action::MasterCount(delta)   => write!(f, "master_count {:+}", delta),
+            Action::Spawn(cmd)   => write!(f, "spawn {}", cmd)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Action {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
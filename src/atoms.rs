@@ -0,0 +1,69 @@
+x11rb::atom_manager! {
+    pub Atoms: AtomsCookie {
+        UTF8_STRING,
+        WM_PROTOCOLS,
+        WM_DELETE_WINDOW,
+        WM_TAKE_FOCUS,
+        WM_WINDOW_ROLE,
+        _NET_SUPPORTED,
+        _NET_SUPPORTING_WM_CHECK,
+        _NET_WM_NAME,
+        _NET_CLIENT_LIST,
+        _NET_CLIENT_LIST_STACKING,
+        _NET_ACTIVE_WINDOW,
+        _NET_CLOSE_WINDOW,
+        _NET_NUMBER_OF_DESKTOPS,
+        _NET_CURRENT_DESKTOP,
+        _NET_WM_DESKTOP,
+        _NET_WM_WINDOW_TYPE,
+        _NET_WM_WINDOW_TYPE_DIALOG,
+        _NET_WM_WINDOW_TYPE_UTILITY,
+        _NET_WM_WINDOW_TYPE_SPLASH,
+        _NET_WM_WINDOW_TYPE_TOOLBAR,
+        _NET_WM_WINDOW_TYPE_DOCK,
+        _NET_WM_STRUT,
+        _NET_WM_STRUT_PARTIAL,
+        _NET_WORKAREA,
+        _NET_WM_STATE,
+        _NET_WM_STATE_FULLSCREEN,
+    }
+}
+
+impl Atoms {
+    // the atoms advertised in _NET_SUPPORTED.
+    pub fn supported(&self) -> Vec<u32> {
+        vec![
+            self._NET_SUPPORTED,
+            self._NET_SUPPORTING_WM_CHECK,
+            self._NET_WM_NAME,
+            self._NET_CLIENT_LIST,
+            self._NET_CLIENT_LIST_STACKING,
+            self._NET_ACTIVE_WINDOW,
+            self._NET_CLOSE_WINDOW,
+            self._NET_NUMBER_OF_DESKTOPS,
+            self._NET_CURRENT_DESKTOP,
+            self._NET_WM_DESKTOP,
+            self._NET_WM_WINDOW_TYPE,
+            self._NET_WM_WINDOW_TYPE_DIALOG,
+            self._NET_WM_WINDOW_TYPE_UTILITY,
+            self._NET_WM_WINDOW_TYPE_SPLASH,
+            self._NET_WM_WINDOW_TYPE_TOOLBAR,
+            self._NET_WM_WINDOW_TYPE_DOCK,
+            self._NET_WM_STRUT,
+            self._NET_WM_STRUT_PARTIAL,
+            self._NET_WORKAREA,
+            self._NET_WM_STATE,
+            self._NET_WM_STATE_FULLSCREEN,
+        ]
+    }
+
+    // the window types that are never tiled.
+    pub fn floating_types(&self) -> [u32; 4] {
+        [
+            self._NET_WM_WINDOW_TYPE_DIALOG,
+            self._NET_WM_WINDOW_TYPE_UTILITY,
+            self._NET_WM_WINDOW_TYPE_SPLASH,
+            self._NET_WM_WINDOW_TYPE_TOOLBAR,
+        ]
+    }
+}
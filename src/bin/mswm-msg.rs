@@ -0,0 +1,56 @@
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::{env, process};
+use serde_json::{json, Value};
+
+use mswm::socket_path::socket_path;
+
+const USAGE: &str = "usage: mswm-msg <action> [argument]
+       mswm-msg get_tree
+       mswm-msg subscribe focus|window|layout|workspace|mode...";
+
+// sends its arguments as a single request to mswm and prints the reply, e.g.
+// `mswm-msg workspace 2` or `mswm-msg spawn xterm`. subscriptions print events until mswm exits.
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let request = match args.first().map(String::as_str) {
+        Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            return;
+        },
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        },
+        Some("get_tree") => json!("get_tree"),
+        Some("subscribe") => json!({ "subscribe": args[1..] }),
+        Some(_) => json!({ "command": args.join(" ") })
+    };
+    let subscribe = request.get("subscribe").is_some();
+
+    if let Err(err) = run(&request, subscribe) {
+        eprintln!("mswm-msg: {}: {}", socket_path().display(), err);
+        process::exit(1);
+    }
+}
+
+fn run(request: &Value, subscribe: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stream = UnixStream::connect(socket_path())?;
+    writeln!(stream, "{}", request)?;
+    let mut lines = BufReader::new(stream).lines();
+
+    let reply: Value = match lines.next() {
+        Some(line) => serde_json::from_str(&line?)?,
+        None => return Err("connection closed without a reply".into())
+    };
+    println!("{}", reply);
+    if reply["success"] != Value::Bool(true) {
+        process::exit(1);
+    }
+    if subscribe {
+        for line in lines {
+            println!("{}", line?);
+        }
+    }
+    Ok(())
+}
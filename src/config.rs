@@ -1,21 +1,421 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::PathBuf;
 use std::process::Command;
+use std::{env, fs, io, thread};
+use inotify::{Inotify, WatchMask};
+use log::{info, warn};
+use serde::{Deserialize, Deserializer};
 use x11rb::errors::ReplyOrIdError;
 use x11rb::protocol::xproto::{ModMask, Button};
 
-pub const MOD_MASK: ModMask = ModMask::M4;
+use crate::action::Action;
+use crate::keybind::{check_bindings, parse_modifier};
+use crate::layout::LayoutRegistry;
+use crate::mousebind::{self, MouseAction};
+use crate::rules::Rule;
+
+// keybinding mode names, the default mode holds the [keybinds] table.
+pub const DEFAULT_MODE: &str = "default";
+
 pub const MOVE_BUTTON: Button = 0x1;
 pub const RESIZE_BUTTON: Button = 0x3;
 
-pub const TEXT_COLOR: u32 = 0xfffafafa;
-pub const TOP_BAR_COLOR: u32 = 0xff224488;
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    #[serde(deserialize_with = "deserialize_mod_mask")]
+    pub mod_mask: ModMask,
+    pub close_timeout_ms: u64,
+    pub chord_timeout_ms: u64,
+    pub focus_model: FocusModel,
+    #[serde(deserialize_with = "deserialize_workspaces")]
+    pub workspaces: usize,
+    pub colors: ColorConfig,
+    pub top_bar: TopBarConfig,
+    pub layout: LayoutConfig,
+    #[serde(deserialize_with = "deserialize_keybinds")]
+    pub keybinds: HashMap<String, Action>,
+    #[serde(deserialize_with = "deserialize_modes")]
+    pub modes: HashMap<String, HashMap<String, Action>>,
+    // without a [mousebinds] section windows are moved and resized with mod_mask.
+    pub mousebinds: Option<MouseBindConfig>,
+    pub rules: Vec<Rule>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FocusModel {
+    FollowsMouse,
+    Click
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorConfig {
+    #[serde(deserialize_with = "deserialize_color")]
+    pub text: u32,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub top_bar: u32
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TopBarConfig {
+    pub height: u16,
+    pub text_offset: i16
+}
+
+// the mouse bindings on clients, on the empty root window and on the top bar.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MouseBindConfig {
+    #[serde(deserialize_with = "deserialize_window_mousebinds")]
+    pub window: HashMap<String, MouseAction>,
+    #[serde(deserialize_with = "deserialize_mousebinds")]
+    pub root: HashMap<String, MouseAction>,
+    #[serde(deserialize_with = "deserialize_mousebinds")]
+    pub bar: HashMap<String, MouseAction>
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayoutConfig {
+    pub default: String,
+    pub min_win_width: i32,
+    #[serde(deserialize_with = "deserialize_master_ratio")]
+    pub master_ratio: f32,
+    pub master_count: usize
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let mut keybinds: HashMap<String, Action> = [
+            ("M4+f",            "layout fibonacci"),
+            ("M4+g",            "layout tree"),
+            ("M4+t",            "layout master"),
+            ("M4+m",            "layout monocle"),
+            ("M4+space",        "next_layout"),
+            ("M4+SHIFT+space",  "prev_layout"),
+            ("M4+h",            "master_ratio -5"),
+            ("M4+l",            "master_ratio +5"),
+            ("M4+i",            "master_count +1"),
+            ("M4+d",            "master_count -1"),
+            ("M4+j",            "stack_inc"),
+            ("M4+k",            "stack_dec"),
+            ("M4+SHIFT+c",      "kill_focused"),
+            ("M4+SHIFT+f",      "toggle_fullscreen"),
+            ("M4+SHIFT+r",      "reload"),
+            ("M4+r",            "mode resize"),
+            ("M4+period",       "focus_monitor next"),
+            ("M4+comma",        "focus_monitor prev"),
+            ("M4+SHIFT+period", "move_to_monitor next"),
+            ("M4+SHIFT+comma",  "move_to_monitor prev"),
+            ("M4+SHIFT+Return", "spawn xterm")
+        ].iter().map(|(k, a)| (k.to_string(), a.parse().unwrap())).collect();
+        for i in 1..=9 {
+            keybinds.insert(format!("M4+{}", i), Action::Workspace(i));
+            keybinds.insert(format!("M4+SHIFT+{}", i), Action::MoveToWorkspace(i));
+        }
+        Self {
+            mod_mask: ModMask::M4,
+            close_timeout_ms: 2000,
+            chord_timeout_ms: 1500,
+            focus_model: FocusModel::FollowsMouse,
+            workspaces: 9,
+            colors: ColorConfig::default(),
+            top_bar: TopBarConfig::default(),
+            layout: LayoutConfig::default(),
+            keybinds,
+            modes: [("resize", [
+                ("h",      "master_ratio -5"),
+                ("l",      "master_ratio +5"),
+                ("j",      "master_count -1"),
+                ("k",      "master_count +1"),
+                ("Return", "mode default")
+            ])].iter().map(|(name, binds)| (name.to_string(), binds.iter()
+                .map(|(k, a)| (k.to_string(), a.parse().unwrap()))
+                .collect())).collect(),
+            mousebinds: None,
+            rules: Vec::new()
+        }
+    }
+}
+
+impl Default for ColorConfig {
+    fn default() -> Self {
+        Self { text: 0xfffafafa, top_bar: 0xff224488 }
+    }
+}
+
+impl Default for TopBarConfig {
+    fn default() -> Self {
+        Self { height: 20, text_offset: 4 }
+    }
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            default: "tree".to_string(),
+            min_win_width: 10,
+            master_ratio: 0.55,
+            master_count: 1
+        }
+    }
+}
 
-pub const TOP_BAR_HEIGHT: u16 = 20;
-pub const TOP_BAR_TEXT_OFFSET: i16 = 4;
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(PathBuf, String)
+}
 
-pub const MIN_WIN_WIDTH: i32 = 10;
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            ConfigError::Parse(path, err) => write!(f, "{}: {}", path.display(), err),
+            ConfigError::Invalid(path, err) => write!(f, "{}: {}", path.display(), err)
+        }
+    }
+}
 
-pub fn spawn_program(program: &str) -> Result<(), ReplyOrIdError> {
-    Command::new(program).spawn().unwrap();
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        let dir = env::var_os("XDG_CONFIG_HOME")
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+        Some(dir.join("mswm").join("config.toml"))
+    }
+
+    // a missing config file is not an error, the defaults are used instead.
+    pub fn load(layouts: &LayoutRegistry) -> Result<Self, ConfigError> {
+        match Self::path() {
+            Some(path) if path.exists() => Self::load_from(path, layouts),
+            _ => Ok(Self::default())
+        }
+    }
+
+    // layouts can be registered at runtime, so their names are only checked after parsing.
+    pub fn load_from(path: PathBuf, layouts: &LayoutRegistry) -> Result<Self, ConfigError> {
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) => return Err(ConfigError::Io(path, err))
+        };
+        let config: Self = match toml::from_str(&content) {
+            Ok(config) => config,
+            Err(err) => return Err(ConfigError::Parse(path, err))
+        };
+        config.check_layouts(layouts).map_err(|err| ConfigError::Invalid(path, err))?;
+        Ok(config)
+    }
+
+    // every layout action of the key and mouse bindings has to name a registered layout.
+    pub fn check_layouts(&self, layouts: &LayoutRegistry) -> Result<(), String> {
+        let mut binds: Vec<(&String, &Action)> = self.keybinds.iter()
+            .chain(self.modes.values().flatten())
+            .chain(self.mousebinds.iter()
+                .flat_map(|m| m.window.iter().chain(&m.root).chain(&m.bar))
+                .filter_map(|(bind, action)| match action {
+                    MouseAction::Action(action) => Some((bind, action)),
+                    _ => None
+                }))
+            .collect();
+        binds.sort_by_key(|(bind, _)| *bind);
+        for (bind, action) in binds {
+            if let Action::Layout(name) = action {
+                if !layouts.contains(name) {
+                    let names: Vec<&str> = layouts.names().collect();
+                    return Err(format!("unknown layout '{}' in '{}', expected one of {}", name, bind, names.join(", ")));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// watches the directory of the config file, since most editors replace the file instead of writing to it.
+pub struct ConfigWatcher {
+    inotify: Option<Inotify>,
+    buffer: [u8; 4096]
+}
+
+impl ConfigWatcher {
+    pub fn new() -> Self {
+        let inotify = Config::path()
+            .and_then(|path| path.parent().map(PathBuf::from))
+            .filter(|dir| dir.is_dir())
+            .and_then(|dir| {
+                let inotify = Inotify::init().ok()?;
+                let mask = WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE | WatchMask::DELETE;
+                match inotify.watches().add(&dir, mask) {
+                    Ok(_) => Some(inotify),
+                    Err(err) => {
+                        warn!("failed to watch {}: {}", dir.display(), err);
+                        None
+                    }
+                }
+            });
+        Self { inotify, buffer: [0; 4096] }
+    }
+
+    pub fn fd(&self) -> Option<RawFd> {
+        self.inotify.as_ref().map(|i| i.as_raw_fd())
+    }
+
+    // drains all pending events and reports whether the config file was touched.
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        if let Some(inotify) = &mut self.inotify {
+            // the inotify fd is non-blocking, so this stops once the queue is empty.
+            while let Ok(events) = inotify.read_events(&mut self.buffer) {
+                for event in events {
+                    changed |= event.name == Some(OsStr::new("config.toml"));
+                }
+            }
+        }
+        changed
+    }
+}
+
+impl Default for ConfigWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn deserialize_mod_mask<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ModMask, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    let mut mask = ModMask::from(0u16);
+    for m in s.split('+') {
+        mask = mask | parse_modifier(m)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown modifier '{}'", m)))?;
+    }
+    Ok(mask)
+}
+
+fn deserialize_keybinds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<String, Action>, D::Error> {
+    let keybinds = HashMap::<String, Action>::deserialize(deserializer)?;
+    check_bindings(keybinds.keys().map(String::as_str)).map_err(serde::de::Error::custom)?;
+    Ok(keybinds)
+}
+
+fn deserialize_modes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<String, HashMap<String, Action>>, D::Error> {
+    let modes = HashMap::<String, HashMap<String, Action>>::deserialize(deserializer)?;
+    for (name, keybinds) in &modes {
+        if name == DEFAULT_MODE {
+            return Err(serde::de::Error::custom("the bindings of the default mode belong in [keybinds]"));
+        }
+        check_bindings(keybinds.keys().map(String::as_str))
+            .map_err(|err| serde::de::Error::custom(format!("{} in mode '{}'", err, name)))?;
+    }
+    Ok(modes)
+}
+
+fn deserialize_window_mousebinds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<String, MouseAction>, D::Error> {
+    let mousebinds = HashMap::<String, MouseAction>::deserialize(deserializer)?;
+    mousebind::check_bindings(mousebinds.keys().map(String::as_str)).map_err(serde::de::Error::custom)?;
+    Ok(mousebinds)
+}
+
+// only windows can be moved and resized.
+fn deserialize_mousebinds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<String, MouseAction>, D::Error> {
+    let mousebinds = deserialize_window_mousebinds(deserializer)?;
+    if let Some((bind, action)) = mousebinds.iter().find(|(_, a)| matches!(a, MouseAction::Move | MouseAction::Resize)) {
+        return Err(serde::de::Error::custom(format!("'{}' can only {} windows", bind, action)));
+    }
+    Ok(mousebinds)
+}
+
+fn deserialize_workspaces<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    match usize::deserialize(deserializer)? {
+        0 => Err(serde::de::Error::custom("at least one workspace is required")),
+        n => Ok(n)
+    }
+}
+
+fn deserialize_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    let invalid = || serde::de::Error::custom(
+        format!("invalid color '{}', expected #rrggbb or #aarrggbb", s));
+    let hex = s.strip_prefix('#').ok_or_else(invalid)?;
+    let color = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+    match hex.len() {
+        6 => Ok(0xff000000 | color),
+        8 => Ok(color),
+        _ => Err(invalid())
+    }
+}
+
+fn deserialize_master_ratio<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    match f32::deserialize(deserializer)? {
+        ratio if ratio > 0.0 && ratio < 1.0 => Ok(ratio),
+        ratio => Err(serde::de::Error::custom(format!("invalid master ratio {}, expected a value between 0 and 1", ratio)))
+    }
+}
+
+pub fn spawn_program(command: &str) -> Result<(), ReplyOrIdError> {
+    let mut args = command.split_whitespace();
+    if let Some(program) = args.next() {
+        match Command::new(program).args(args).spawn() {
+            // reap the child once it exits so it does not linger as a zombie.
+            Ok(mut child) => {
+                info!("spawned '{}' as process {}", command, child.id());
+                thread::spawn(move || child.wait());
+            },
+            Err(err) => warn!("failed to spawn '{}': {}", command, err)
+        }
+    }
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // loads content from a file of its own, errors are returned as their message.
+    fn load(name: &str, content: &str) -> (PathBuf, Result<Config, String>) {
+        let path = env::temp_dir().join(format!("mswm-test-{}-{}.toml", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        let result = Config::load_from(path.clone(), &LayoutRegistry::default()).map_err(|err| err.to_string());
+        fs::remove_file(&path).unwrap();
+        (path, result)
+    }
+
+    #[test]
+    fn unknown_keysyms_in_the_config_file_are_reported_with_the_file() {
+        let (path, result) = load("keysym", "[keybinds]\n\"M4+nokey\" = \"stack_inc\"\n");
+        let err = result.unwrap_err();
+        assert!(err.starts_with(&path.display().to_string()));
+        assert!(err.contains("unknown key 'nokey' in 'M4+nokey'"));
+    }
+
+    #[test]
+    fn unknown_fields_in_the_config_file_are_reported_with_file_and_line() {
+        let (path, result) = load("field", "close_timeout_ms = 500\n\n[layout]\nmin_win_width = 10\ngaps = 4\n");
+        let err = result.unwrap_err();
+        assert!(err.starts_with(&path.display().to_string()));
+        assert!(err.contains("line 5"), "{}", err);
+        assert!(err.contains("unknown field `gaps`"), "{}", err);
+    }
+
+    #[test]
+    fn unknown_layouts_in_bindings_are_rejected_when_loading() {
+        let layouts = LayoutRegistry::default();
+        let check = |toml: &str| toml::from_str::<Config>(toml).unwrap().check_layouts(&layouts);
+
+        assert!(check("[keybinds]\n\"M4+f\" = \"layout fibonacci\"").is_ok());
+        assert!(check("[keybinds]\n\"M4+f\" = \"layout fibonaci\"").unwrap_err()
+            .starts_with("unknown layout 'fibonaci' in 'M4+f', expected one of tree"));
+        assert!(check("[modes.resize]\n\"f\" = \"layout grid\"").unwrap_err().contains("in 'f'"));
+        assert!(check("[mousebinds.bar]\n\"left\" = \"layout grid\"").unwrap_err().contains("in 'left'"));
+        assert!(Config::default().check_layouts(&layouts).is_ok());
+    }
+}
@@ -0,0 +1,89 @@
+use std::fmt;
+use log::{debug, warn};
+use x11rb::errors::{ConnectionError, ReplyError, ReplyOrIdError};
+use x11rb::protocol::ErrorKind;
+use x11rb::x11_utils::X11Error;
+
+// what went wrong while talking to the X server. clients can disappear at any time, so errors about
+// a single window are expected and only logged, while a broken connection ends the session.
+#[derive(Debug)]
+pub enum Error {
+    Connection(ConnectionError),
+    IdsExhausted,
+    X11(X11Error)
+}
+
+impl Error {
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            Error::Connection(_) | Error::IdsExhausted => true,
+            Error::X11(_) => false
+        }
+    }
+
+    // whether the error was caused by a window that vanished or did not fit the request.
+    pub fn is_window_error(&self) -> bool {
+        matches!(self, Error::X11(err) if matches!(err.error_kind, ErrorKind::Window | ErrorKind::Drawable | ErrorKind::Match))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Connection(err) => write!(f, "connection to the X server failed: {}", err),
+            Error::IdsExhausted => write!(f, "ran out of X resource ids"),
+            Error::X11(err) => write!(
+                f, "X error {:?} for resource {:#x} in request {}.{}",
+                err.error_kind, err.bad_value, err.major_opcode, err.minor_opcode
+            )
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ConnectionError> for Error {
+    fn from(err: ConnectionError) -> Self {
+        Error::Connection(err)
+    }
+}
+
+impl From<X11Error> for Error {
+    fn from(err: X11Error) -> Self {
+        Error::X11(err)
+    }
+}
+
+impl From<ReplyError> for Error {
+    fn from(err: ReplyError) -> Self {
+        match err {
+            ReplyError::ConnectionError(err) => Error::Connection(err),
+            ReplyError::X11Error(err) => Error::X11(err)
+        }
+    }
+}
+
+impl From<ReplyOrIdError> for Error {
+    fn from(err: ReplyOrIdError) -> Self {
+        match err {
+            ReplyOrIdError::ConnectionError(err) => Error::Connection(err),
+            ReplyOrIdError::IdsExhausted => Error::IdsExhausted,
+            ReplyOrIdError::X11Error(err) => Error::X11(err)
+        }
+    }
+}
+
+// logs errors that leave the WM in a usable state and passes on the fatal ones.
+pub fn recover<E: Into<Error>>(result: Result<(), E>) -> Result<(), Error> {
+    match result.map_err(Into::into) {
+        Err(err) if !err.is_fatal() => {
+            if err.is_window_error() {
+                debug!("ignoring {}, the window is probably gone", err);
+            } else {
+                warn!("{}", err);
+            }
+            Ok(())
+        },
+        result => result
+    }
+}
@@ -0,0 +1,488 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::io::IoSlice;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use x11rb::connection::{
+    Connection, DiscardMode, RawEventAndSeqNumber, ReplyOrError, RequestConnection, RequestKind, SequenceNumber
+};
+use x11rb::cookie::{Cookie, CookieWithFds, VoidCookie};
+use x11rb::errors::{ConnectionError, ParseError, ReplyOrIdError};
+use x11rb::protocol::xproto::*;
+use x11rb::protocol::{Event, Request};
+use x11rb::utils::RawFdContainer;
+use x11rb::x11_utils::{
+    parse_request_header, BigRequests, ExtInfoProvider, ExtensionInformation, Serialize, TryParse, TryParseFd, X11Error
+};
+use xkbcommon::xkb;
+
+use crate::wm::XConnection;
+
+pub const ROOT: Window = 0x100;
+const MIN_KEYCODE: Keycode = 8;
+// the atoms predefined by the core protocol end with WM_TRANSIENT_FOR.
+const LAST_PREDEFINED_ATOM: Atom = 68;
+// the keys the default keybinds refer to and Num Lock.
+const KEYS: &[&str] = &[
+    "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m",
+    "n", "o", "p", "q", "r", "s", "t", "u", "v", "w", "x", "y", "z",
+    "1", "2", "3", "4", "5", "6", "7", "8", "9", "0",
+    "space", "Return", "Escape", "period", "comma", "Num_Lock"
+];
+
+// no extensions are available, so RandR is off and a single monitor covers the root window.
+struct NoExtensions;
+
+impl ExtInfoProvider for NoExtensions {
+    fn get_from_major_opcode(&self, _: u8) -> Option<(&str, ExtensionInformation)> { None }
+    fn get_from_event_code(&self, _: u8) -> Option<(&str, ExtensionInformation)> { None }
+    fn get_from_error_code(&self, _: u8) -> Option<(&str, ExtensionInformation)> { None }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FakeWindow {
+    pub parent: Window,
+    pub rect: Rectangle,
+    pub border: u16,
+    pub mapped: bool,
+    pub override_redirect: bool,
+    // property -> (type, format, data)
+    pub properties: HashMap<Atom, (Atom, u8, Vec<u8>)>
+}
+
+#[derive(Default)]
+struct State {
+    sequence: SequenceNumber,
+    next_id: u32,
+    next_client: Window,
+    requests: Vec<Request<'static>>,
+    responses: HashMap<SequenceNumber, ReplyOrError<Vec<u8>>>,
+    events: VecDeque<Vec<u8>>,
+    atoms: Vec<Vec<u8>>,
+    // the key names by keycode, starting at MIN_KEYCODE.
+    keys: Vec<&'static str>,
+    keyboard_grab: Option<Window>,
+    // passive key grabs of other clients as (window, modifiers, key).
+    foreign_grabs: Vec<(Window, u16, Keycode)>,
+    // in stacking order from bottom to top.
+    windows: Vec<(Window, FakeWindow)>
+}
+
+impl State {
+    fn window(&self, win: Window) -> Option<&FakeWindow> {
+        self.windows.iter().find(|(w, _)| *w == win).map(|(_, window)| window)
+    }
+
+    fn window_mut(&mut self, win: Window) -> Option<&mut FakeWindow> {
+        self.windows.iter_mut().find(|(w, _)| *w == win).map(|(_, window)| window)
+    }
+
+    fn atom(&mut self, name: &[u8]) -> Atom {
+        match self.atoms.iter().position(|a| a == name) {
+            Some(i) => LAST_PREDEFINED_ATOM + 1 + i as Atom,
+            None => {
+                self.atoms.push(name.to_vec());
+                LAST_PREDEFINED_ATOM + self.atoms.len() as Atom
+            }
+        }
+    }
+
+    // applies the effect of a request and returns its reply, if it has one.
+    fn execute(&mut self, request: &Request<'_>, setup: &Setup) -> Option<Result<Vec<u8>, (u8, u32)>> {
+        let seq = self.sequence as u16;
+        let bad_window = |win| Some(Err((ERROR_BAD_WINDOW, win)));
+        match request {
+            Request::CreateWindow(r) => {
+                let rect = Rectangle { x: r.x, y: r.y, width: r.width, height: r.height };
+                let override_redirect = r.value_list.override_redirect.is_some_and(|o| o != 0);
+                let window = FakeWindow { parent: r.parent, rect, border: r.border_width, override_redirect, ..Default::default() };
+                self.windows.push((r.wid, window));
+            },
+            Request::DestroyWindow(r) => self.windows.retain(|(w, _)| *w != r.window),
+            Request::MapWindow(r) => if let Some(w) = self.window_mut(r.window) { w.mapped = true },
+            Request::UnmapWindow(r) => if let Some(w) = self.window_mut(r.window) { w.mapped = false },
+            Request::ReparentWindow(r) => if let Some(w) = self.window_mut(r.window) {
+                w.parent = r.parent;
+                w.rect.x = r.x;
+                w.rect.y = r.y;
+            },
+            Request::ConfigureWindow(r) => {
+                let aux = &r.value_list;
+                if let Some(w) = self.window_mut(r.window) {
+                    w.rect.x = aux.x.map_or(w.rect.x, |x| x as i16);
+                    w.rect.y = aux.y.map_or(w.rect.y, |y| y as i16);
+                    w.rect.width = aux.width.map_or(w.rect.width, |w| w as u16);
+                    w.rect.height = aux.height.map_or(w.rect.height, |h| h as u16);
+                    w.border = aux.border_width.map_or(w.border, |b| b as u16);
+                }
+                if let Some(i) = self.windows.iter().position(|(w, _)| *w == r.window) {
+                    match aux.stack_mode {
+                        Some(StackMode::ABOVE) => { let w = self.windows.remove(i); self.windows.push(w); },
+                        Some(StackMode::BELOW) => { let w = self.windows.remove(i); self.windows.insert(0, w); },
+                        _ => {}
+                    }
+                }
+            },
+            Request::ChangeProperty(r) => if let Some(w) = self.window_mut(r.window) {
+                let value = w.properties.entry(r.property).or_insert((r.type_, r.format, Vec::new()));
+                if r.mode == PropMode::REPLACE || value.0 != r.type_ || value.1 != r.format {
+                    *value = (r.type_, r.format, Vec::new());
+                }
+                if r.mode == PropMode::PREPEND {
+                    value.2.splice(0..0, r.data.iter().copied());
+                } else {
+                    value.2.extend_from_slice(&r.data);
+                }
+            },
+            Request::DeleteProperty(r) => if let Some(w) = self.window_mut(r.window) {
+                w.properties.remove(&r.property);
+            },
+            Request::InternAtom(r) => {
+                let atom = self.atom(&r.name);
+                return Some(Ok(reply(&InternAtomReply { sequence: seq, length: 0, atom })));
+            },
+            Request::GetGeometry(r) => {
+                let Some(w) = self.window(r.drawable) else { return Some(Err((ERROR_BAD_DRAWABLE, r.drawable))) };
+                return Some(Ok(reply(&GetGeometryReply {
+                    depth: 24, sequence: seq, length: 0, root: ROOT,
+                    x: w.rect.x, y: w.rect.y, width: w.rect.width, height: w.rect.height, border_width: w.border
+                })));
+            },
+            Request::GetWindowAttributes(r) => {
+                let Some(w) = self.window(r.window) else { return bad_window(r.window) };
+                let map_state = if w.mapped { MapState::VIEWABLE } else { MapState::UNMAPPED };
+                return Some(Ok(reply(&GetWindowAttributesReply {
+                    sequence: seq, map_state, override_redirect: w.override_redirect, ..Default::default()
+                })));
+            },
+            Request::QueryTree(r) => {
+                let Some(w) = self.window(r.window) else { return bad_window(r.window) };
+                let parent = w.parent;
+                let children = self.windows.iter().filter(|(_, c)| c.parent == r.window).map(|(w, _)| *w).collect();
+                return Some(Ok(reply(&QueryTreeReply { sequence: seq, length: 0, root: ROOT, parent, children })));
+            },
+            Request::GetProperty(r) => {
+                let Some(w) = self.window(r.window) else { return bad_window(r.window) };
+                let property = match w.properties.get(&r.property) {
+                    Some((type_, format, data)) if r.type_ == u32::from(AtomEnum::ANY) || r.type_ == *type_ => {
+                        let unit = *format as usize / 8;
+                        let end = data.len().min((r.long_offset as usize + r.long_length as usize).saturating_mul(4));
+                        let value = data.get(r.long_offset as usize * 4..end).unwrap_or_default().to_vec();
+                        GetPropertyReply {
+                            format: *format, sequence: seq, length: 0, type_: *type_,
+                            bytes_after: (data.len() - end) as u32, value_len: (value.len() / unit) as u32, value
+                        }
+                    },
+                    Some((type_, format, data)) => GetPropertyReply {
+                        format: *format, sequence: seq, type_: *type_, bytes_after: data.len() as u32, ..Default::default()
+                    },
+                    None => GetPropertyReply { sequence: seq, ..Default::default() }
+                };
+                return Some(Ok(reply(&property)));
+            },
+            Request::GetKeyboardMapping(r) => {
+                let keysyms = (r.first_keycode..r.first_keycode + r.count)
+                    .map(|keycode| self.keys.get((keycode - setup.min_keycode) as usize)
+                        .map_or(0, |name| xkb::keysym_from_name(name, xkb::KEYSYM_NO_FLAGS)))
+                    .collect();
+                return Some(Ok(reply(&GetKeyboardMappingReply { keysyms_per_keycode: 1, sequence: seq, keysyms })));
+            },
+            Request::GetModifierMapping(_) => {
+                // one keycode per modifier from SHIFT to M5, Num Lock is M2 as on most setups.
+                let num_lock = self.keys.iter().position(|k| *k == "Num_Lock").map_or(0, |i| MIN_KEYCODE + i as u8);
+                let keycodes = vec![0, 0, 0, 0, num_lock, 0, 0, 0];
+                return Some(Ok(reply(&GetModifierMappingReply { sequence: seq, length: 0, keycodes })));
+            },
+            Request::GrabKey(r) if self.foreign_grabs.contains(&(r.grab_window, u16::from(r.modifiers), r.key)) => {
+                return Some(Err((ERROR_BAD_ACCESS, r.grab_window)));
+            },
+            Request::GrabKeyboard(r) if self.keyboard_grab.is_some_and(|w| w != r.grab_window) => {
+                return Some(Ok(reply(&GrabKeyboardReply { status: GrabStatus::ALREADY_GRABBED, sequence: seq, length: 0 })));
+            },
+            Request::GrabKeyboard(r) => {
+                self.keyboard_grab = Some(r.grab_window);
+                return Some(Ok(reply(&GrabKeyboardReply { status: GrabStatus::SUCCESS, sequence: seq, length: 0 })));
+            },
+            Request::UngrabKeyboard(_) => self.keyboard_grab = None,
+            Request::GetInputFocus(_) => {
+                return Some(Ok(reply(&GetInputFocusReply { sequence: seq, ..Default::default() })));
+            },
+            _ => {}
+        }
+        None
+    }
+}
+
+const ERROR_BAD_WINDOW: u8 = 3;
+const ERROR_BAD_ACCESS: u8 = 10;
+const ERROR_BAD_DRAWABLE: u8 = 9;
+
+// serializes a reply and fixes up its length field, which counts the 4 byte units after the first 32 bytes.
+fn reply(reply: &impl Serialize<Bytes = impl AsRef<[u8]>>) -> Vec<u8> {
+    let mut bytes = reply.serialize().as_ref().to_vec();
+    bytes.resize(bytes.len().max(32).next_multiple_of(4), 0);
+    let length = ((bytes.len() - 32) / 4) as u32;
+    bytes[4..8].copy_from_slice(&length.to_ne_bytes());
+    bytes
+}
+
+fn error(code: u8, sequence: u16, bad_value: u32, major_opcode: u8) -> Vec<u8> {
+    let mut bytes = vec![0; 32];
+    bytes[1] = code;
+    bytes[2..4].copy_from_slice(&sequence.to_ne_bytes());
+    bytes[4..8].copy_from_slice(&bad_value.to_ne_bytes());
+    bytes[10] = major_opcode;
+    bytes
+}
+
+// an in-memory X server that records every request it receives. it keeps track of the window tree
+// and properties to answer the queries the WM makes, events have to be injected by the test.
+pub struct FakeConnection {
+    setup: Setup,
+    state: RefCell<State>,
+    // never becomes readable, the WM only polls it when no event is queued.
+    socket: (UnixStream, UnixStream)
+}
+
+impl FakeConnection {
+    pub fn new(width: u16, height: u16) -> Self {
+        let screen = Screen {
+            root: ROOT,
+            white_pixel: 0xffffff,
+            width_in_pixels: width,
+            height_in_pixels: height,
+            root_depth: 24,
+            ..Default::default()
+        };
+        let setup = Setup {
+            resource_id_base: 0x200000,
+            resource_id_mask: 0x1fffff,
+            min_keycode: MIN_KEYCODE,
+            max_keycode: MIN_KEYCODE + KEYS.len() as u8 - 1,
+            roots: vec![screen],
+            ..Default::default()
+        };
+        let root = FakeWindow { rect: Rectangle { x: 0, y: 0, width, height }, mapped: true, ..Default::default() };
+        let state = State { next_client: 0x400000, keys: KEYS.to_vec(), windows: vec![(ROOT, root)], ..Default::default() };
+        Self { setup, state: RefCell::new(state), socket: UnixStream::pair().expect("failed to create a socket pair") }
+    }
+
+    // creates an unmapped top-level window like a client would.
+    pub fn create_client(&self, rect: Rectangle) -> Window {
+        let mut state = self.state.borrow_mut();
+        let win = state.next_client;
+        state.next_client += 1;
+        state.windows.push((win, FakeWindow { parent: ROOT, rect, ..Default::default() }));
+        win
+    }
+
+    pub fn set_property(&self, win: Window, property: Atom, type_: Atom, format: u8, data: &[u8]) {
+        if let Some(w) = self.state.borrow_mut().window_mut(win) {
+            w.properties.insert(property, (type_, format, data.to_vec()));
+        }
+    }
+
+    pub fn window(&self, win: Window) -> Option<FakeWindow> {
+        self.state.borrow().window(win).cloned()
+    }
+
+    // makes grabbing the key on the window fail as if another client grabbed it first.
+    pub fn grab_key_elsewhere(&self, win: Window, modifiers: ModMask, key: &str) {
+        let key = self.keycode(key);
+        self.state.borrow_mut().foreign_grabs.push((win, modifiers.into(), key));
+    }
+
+    // makes grabbing the keyboard fail as if a menu or a screen locker held it.
+    pub fn grab_keyboard_elsewhere(&self, win: Window) {
+        self.state.borrow_mut().keyboard_grab = Some(win);
+    }
+
+    pub fn keyboard_grab(&self) -> Option<Window> {
+        self.state.borrow().keyboard_grab
+    }
+
+    pub fn keycode(&self, name: &str) -> Keycode {
+        let i = self.state.borrow().keys.iter().position(|k| *k == name).unwrap_or_else(|| panic!("unknown key {}", name));
+        MIN_KEYCODE + i as u8
+    }
+
+    // queues an event, which carries the sequence number of the last request like a real one would.
+    pub fn push_event(&self, event: impl Into<[u8; 32]>) {
+        let mut state = self.state.borrow_mut();
+        let mut bytes = event.into().to_vec();
+        bytes[2..4].copy_from_slice(&(state.sequence as u16).to_ne_bytes());
+        state.events.push_back(bytes);
+    }
+
+    pub fn map_request(&self, win: Window) {
+        self.push_event(MapRequestEvent { response_type: MAP_REQUEST_EVENT, parent: ROOT, window: win, ..Default::default() });
+    }
+
+    pub fn key_press(&self, state: KeyButMask, key: &str) {
+        self.push_event(KeyPressEvent {
+            response_type: KEY_PRESS_EVENT, detail: self.keycode(key), root: ROOT, event: ROOT, state, same_screen: true,
+            ..Default::default()
+        });
+    }
+
+    // exchanges the keycodes of two keys like a layout switch would, clients are told with a MappingNotify.
+    pub fn swap_keys(&self, a: &str, b: &str) {
+        let (a, b) = (self.keycode(a), self.keycode(b));
+        self.state.borrow_mut().keys.swap((a - MIN_KEYCODE) as usize, (b - MIN_KEYCODE) as usize);
+        self.push_event(MappingNotifyEvent {
+            response_type: MAPPING_NOTIFY_EVENT, request: Mapping::KEYBOARD, first_keycode: MIN_KEYCODE,
+            count: KEYS.len() as u8, ..Default::default()
+        });
+    }
+
+    // a click reported on the window, clicks on the root window name the top-level child below the pointer.
+    pub fn button_press(&self, win: Window, child: Window, state: KeyButMask, button: Button, x: i16, y: i16) {
+        self.push_event(ButtonPressEvent {
+            response_type: BUTTON_PRESS_EVENT, detail: button, root: ROOT, event: win, child,
+            root_x: x, root_y: y, event_x: x, event_y: y, state, same_screen: true, ..Default::default()
+        });
+    }
+
+    pub fn button_release(&self, win: Window, button: Button) {
+        self.push_event(ButtonReleaseEvent {
+            response_type: BUTTON_RELEASE_EVENT, detail: button, root: ROOT, event: win, same_screen: true,
+            ..Default::default()
+        });
+    }
+
+    pub fn motion_notify(&self, win: Window, x: i16, y: i16) {
+        self.push_event(MotionNotifyEvent {
+            response_type: MOTION_NOTIFY_EVENT, root: ROOT, event: win, root_x: x, root_y: y, same_screen: true,
+            ..Default::default()
+        });
+    }
+
+    pub fn enter_notify(&self, win: Window) {
+        self.push_event(EnterNotifyEvent {
+            response_type: ENTER_NOTIFY_EVENT, root: ROOT, event: win, mode: NotifyMode::NORMAL, ..Default::default()
+        });
+    }
+
+    // returns the requests received so far and forgets them.
+    pub fn take_requests(&self) -> Vec<Request<'static>> {
+        std::mem::take(&mut self.state.borrow_mut().requests)
+    }
+
+    fn send(&self, bufs: &[IoSlice<'_>]) -> Result<SequenceNumber, ConnectionError> {
+        let bytes: Vec<u8> = bufs.iter().flat_map(|b| b.iter().copied()).collect();
+        let (header, body) = parse_request_header(&bytes, BigRequests::NotEnabled)?;
+        let request = Request::parse(header, body, &mut Vec::new(), &NoExtensions)?.into_owned();
+        let mut state = self.state.borrow_mut();
+        state.sequence += 1;
+        let seq = state.sequence;
+        match state.execute(&request, &self.setup) {
+            Some(Ok(reply)) => { state.responses.insert(seq, ReplyOrError::Reply(reply)); },
+            Some(Err((code, value))) => {
+                state.responses.insert(seq, ReplyOrError::Error(error(code, seq as u16, value, header.major_opcode)));
+            },
+            None => {}
+        }
+        state.requests.push(request);
+        Ok(seq)
+    }
+}
+
+impl RequestConnection for FakeConnection {
+    type Buf = Vec<u8>;
+
+    fn send_request_with_reply<R: TryParse>(&self, bufs: &[IoSlice<'_>], _: Vec<RawFdContainer>)
+        -> Result<Cookie<'_, Self, R>, ConnectionError> {
+        Ok(Cookie::new(self, self.send(bufs)?))
+    }
+
+    fn send_request_with_reply_with_fds<R: TryParseFd>(&self, _: &[IoSlice<'_>], _: Vec<RawFdContainer>)
+        -> Result<CookieWithFds<'_, Self, R>, ConnectionError> {
+        Err(ConnectionError::FdPassingFailed)
+    }
+
+    fn send_request_without_reply(&self, bufs: &[IoSlice<'_>], _: Vec<RawFdContainer>)
+        -> Result<VoidCookie<'_, Self>, ConnectionError> {
+        Ok(VoidCookie::new(self, self.send(bufs)?))
+    }
+
+    fn discard_reply(&self, sequence: SequenceNumber, _: RequestKind, _: DiscardMode) {
+        self.state.borrow_mut().responses.remove(&sequence);
+    }
+
+    fn prefetch_extension_information(&self, _: &'static str) -> Result<(), ConnectionError> {
+        Ok(())
+    }
+
+    fn extension_information(&self, _: &'static str) -> Result<Option<ExtensionInformation>, ConnectionError> {
+        Ok(None)
+    }
+
+    fn wait_for_reply_or_raw_error(&self, sequence: SequenceNumber) -> Result<ReplyOrError<Vec<u8>>, ConnectionError> {
+        self.state.borrow_mut().responses.remove(&sequence).ok_or(ConnectionError::UnknownError)
+    }
+
+    fn wait_for_reply(&self, sequence: SequenceNumber) -> Result<Option<Vec<u8>>, ConnectionError> {
+        match self.state.borrow_mut().responses.remove(&sequence) {
+            Some(ReplyOrError::Reply(reply)) => Ok(Some(reply)),
+            _ => Ok(None)
+        }
+    }
+
+    fn wait_for_reply_with_fds_raw(&self, _: SequenceNumber)
+        -> Result<ReplyOrError<(Vec<u8>, Vec<RawFdContainer>), Vec<u8>>, ConnectionError> {
+        Err(ConnectionError::FdPassingFailed)
+    }
+
+    fn check_for_raw_error(&self, sequence: SequenceNumber) -> Result<Option<Vec<u8>>, ConnectionError> {
+        match self.state.borrow_mut().responses.remove(&sequence) {
+            Some(ReplyOrError::Error(error)) => Ok(Some(error)),
+            _ => Ok(None)
+        }
+    }
+
+    fn prefetch_maximum_request_bytes(&self) {}
+
+    fn maximum_request_bytes(&self) -> usize {
+        4 * 0xffff
+    }
+
+    fn parse_error(&self, error: &[u8]) -> Result<X11Error, ParseError> {
+        X11Error::try_parse(error, &NoExtensions)
+    }
+
+    fn parse_event(&self, event: &[u8]) -> Result<Event, ParseError> {
+        Event::parse(event, &NoExtensions)
+    }
+}
+
+impl Connection for FakeConnection {
+    fn wait_for_raw_event_with_sequence(&self) -> Result<RawEventAndSeqNumber<Vec<u8>>, ConnectionError> {
+        self.poll_for_raw_event_with_sequence()?.ok_or(ConnectionError::UnknownError)
+    }
+
+    fn poll_for_raw_event_with_sequence(&self) -> Result<Option<RawEventAndSeqNumber<Vec<u8>>>, ConnectionError> {
+        let mut state = self.state.borrow_mut();
+        let sequence = state.sequence;
+        Ok(state.events.pop_front().map(|event| (event, sequence)))
+    }
+
+    fn flush(&self) -> Result<(), ConnectionError> {
+        Ok(())
+    }
+
+    fn setup(&self) -> &Setup {
+        &self.setup
+    }
+
+    fn generate_id(&self) -> Result<u32, ReplyOrIdError> {
+        let mut state = self.state.borrow_mut();
+        state.next_id += 1;
+        Ok(self.setup.resource_id_base | state.next_id)
+    }
+}
+
+impl XConnection for FakeConnection {
+    fn raw_fd(&self) -> RawFd {
+        self.socket.0.as_raw_fd()
+    }
+}
@@ -0,0 +1,277 @@
+use std::fs::{self, DirBuilder, Permissions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use nix::unistd::getuid;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::action::Action;
+
+// clients sending longer lines than this are disconnected.
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+// a single line of JSON sent by a client, e.g. {"command": "workspace 2"},
+// {"subscribe": ["focus", "workspace"]} or "get_tree".
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Request {
+    Command(Action),
+    Subscribe(Vec<EventKind>),
+    GetTree
+}
+
+// the kinds of events a client can subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Focus,
+    Window,
+    Layout,
+    Workspace,
+    Mode
+}
+
+impl EventKind {
+    fn name(self) -> &'static str {
+        match self {
+            EventKind::Focus     => "focus",
+            EventKind::Window    => "window",
+            EventKind::Layout    => "layout",
+            EventKind::Workspace => "workspace",
+            EventKind::Mode      => "mode"
+        }
+    }
+}
+
+struct Client {
+    stream: UnixStream,
+    buffer: Vec<u8>,
+    subscriptions: Vec<EventKind>,
+    closed: bool
+}
+
+impl Client {
+    // reads whatever is available without blocking and returns the complete lines.
+    fn read_lines(&mut self) -> Vec<String> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => { self.closed = true; break; },
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => { self.closed = true; break; }
+            }
+        }
+        let mut lines = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            lines.push(String::from_utf8_lossy(&line).trim().to_string());
+        }
+        if self.buffer.len() > MAX_LINE_LENGTH {
+            self.closed = true;
+        }
+        lines.retain(|line| !line.is_empty());
+        lines
+    }
+
+    // replies are written blocking, but a client that stops reading is dropped after a short while.
+    fn send(&mut self, message: &Value) {
+        let mut line = message.to_string();
+        line.push('\n');
+        let result = self.stream.set_nonblocking(false)
+            .and_then(|_| self.stream.set_write_timeout(Some(Duration::from_millis(100))))
+            .and_then(|_| self.stream.write_all(line.as_bytes()))
+            .and_then(|_| self.stream.set_nonblocking(true));
+        if result.is_err() {
+            self.closed = true;
+        }
+    }
+}
+
+// the Unix socket scripts use to control mswm, see mswm-msg. clients are identified by their
+// position in the list of connections, which stays valid until the next call to read_requests.
+pub struct IpcServer {
+    path: PathBuf,
+    listener: UnixListener,
+    clients: Vec<Client>
+}
+
+impl IpcServer {
+    // whoever can connect can spawn programs, so the socket only goes into a directory that
+    // belongs to the user and is closed to everyone else. that directory is what keeps others out
+    // between binding and restricting the socket itself.
+    pub fn bind(path: &Path) -> io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+            let metadata = fs::metadata(dir)?;
+            if metadata.uid() != getuid().as_raw() {
+                return Err(io::Error::new(ErrorKind::PermissionDenied,
+                    format!("{} belongs to another user", dir.display())));
+            }
+            if metadata.mode() & 0o077 != 0 {
+                return Err(io::Error::new(ErrorKind::PermissionDenied,
+                    format!("{} is accessible to other users", dir.display())));
+            }
+        }
+        // a socket left behind by a previous instance would make binding fail.
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, Permissions::from_mode(0o600))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { path: path.to_path_buf(), listener, clients: Vec::new() })
+    }
+
+    pub fn fds(&self) -> Vec<RawFd> {
+        let mut fds = vec![self.listener.as_raw_fd()];
+        fds.extend(self.clients.iter().map(|c| c.stream.as_raw_fd()));
+        fds
+    }
+
+    // accepts new connections and collects the requests that arrived in the meantime.
+    pub fn read_requests(&mut self) -> Vec<(usize, Result<Request, String>)> {
+        self.clients.retain(|c| !c.closed);
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if stream.set_nonblocking(true).is_ok() {
+                        self.clients.push(Client { stream, buffer: Vec::new(), subscriptions: Vec::new(), closed: false });
+                    }
+                },
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => break
+            }
+        }
+        let mut requests = Vec::new();
+        for (i, client) in self.clients.iter_mut().enumerate() {
+            for line in client.read_lines() {
+                requests.push((i, serde_json::from_str(&line).map_err(|err| err.to_string())));
+            }
+        }
+        requests
+    }
+
+    pub fn reply(&mut self, client: usize, result: Result<Value, String>) {
+        let message = match result {
+            Ok(Value::Null) => json!({ "success": true }),
+            Ok(data) => json!({ "success": true, "data": data }),
+            Err(error) => json!({ "success": false, "error": error })
+        };
+        if let Some(client) = self.clients.get_mut(client) {
+            client.send(&message);
+        }
+    }
+
+    pub fn subscribe(&mut self, client: usize, kinds: &[EventKind]) {
+        if let Some(client) = self.clients.get_mut(client) {
+            for kind in kinds {
+                if !client.subscriptions.contains(kind) {
+                    client.subscriptions.push(*kind);
+                }
+            }
+        }
+    }
+
+    // sends the event to every client subscribed to its kind, data has to be a JSON object.
+    pub fn broadcast(&mut self, kind: EventKind, mut data: Value) {
+        if let Some(fields) = data.as_object_mut() {
+            fields.insert("event".to_string(), Value::from(kind.name()));
+        }
+        for client in self.clients.iter_mut().filter(|c| !c.closed && c.subscriptions.contains(&kind)) {
+            client.send(&data);
+        }
+    }
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mswm-test-{}-{}", std::process::id(), name))
+    }
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn the_socket_is_private_to_the_user() {
+        let dir = test_dir("private");
+        let path = dir.join("mswm.sock");
+        let server = IpcServer::bind(&path).unwrap();
+        assert_eq!(mode(&dir), 0o700);
+        assert_eq!(mode(&path), 0o600);
+        drop(server);
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn directories_open_to_other_users_are_refused() {
+        let dir = test_dir("shared");
+        fs::create_dir(&dir).unwrap();
+        fs::set_permissions(&dir, Permissions::from_mode(0o755)).unwrap();
+        let err = IpcServer::bind(&dir.join("mswm.sock")).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        assert!(!dir.join("mswm.sock").exists());
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    fn parse(line: &str) -> Result<Request, serde_json::Error> {
+        serde_json::from_str(line)
+    }
+
+    #[test]
+    fn requests_are_parsed_from_json() {
+        assert!(matches!(parse(r#"{"command": "workspace 2"}"#),
+            Ok(Request::Command(action)) if action == "workspace 2".parse().unwrap()));
+        assert!(matches!(parse(r#"{"subscribe": ["focus", "mode"]}"#),
+            Ok(Request::Subscribe(kinds)) if kinds == [EventKind::Focus, EventKind::Mode]));
+        assert!(matches!(parse(r#""get_tree""#), Ok(Request::GetTree)));
+    }
+
+    #[test]
+    fn malformed_requests_are_rejected() {
+        for line in [
+            r#"{"command": "fly away"}"#, r#"{"subscribe": ["weather"]}"#, r#"{"subscribe": "focus"}"#,
+            r#""get_window""#, r#"{"command": "reload", "subscribe": []}"#, "workspace 2", "{"
+        ] {
+            assert!(parse(line).is_err(), "{} was accepted", line);
+        }
+    }
+
+    #[test]
+    fn requests_split_across_writes_are_joined_and_long_lines_disconnect() {
+        let dir = test_dir("lines");
+        let mut server = IpcServer::bind(&dir.join("mswm.sock")).unwrap();
+        let mut client = UnixStream::connect(dir.join("mswm.sock")).unwrap();
+
+        client.write_all(b"\"get_tr").unwrap();
+        assert!(server.read_requests().is_empty());
+        client.write_all(b"ee\"\n\n{\"command\": \"fly\"}\n").unwrap();
+        let requests = server.read_requests();
+        assert_eq!(requests.len(), 2);
+        assert!(matches!(requests[0], (0, Ok(Request::GetTree))));
+        assert!(matches!(requests[1], (0, Err(_))));
+
+        client.write_all(&vec![b'x'; MAX_LINE_LENGTH + 1]).unwrap();
+        assert!(server.read_requests().is_empty());
+        assert!(server.read_requests().is_empty());
+        assert!(server.clients.is_empty());
+        drop(server);
+        fs::remove_dir(&dir).unwrap();
+    }
+}
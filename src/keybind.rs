@@ -1,107 +1,451 @@
-use std::collections::HashMap;
-use std::error::Error;
-use std::sync::Mutex;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::time::{Duration, Instant};
+use log::{debug, warn};
 use x11rb::connection::Connection;
 use x11rb::errors::{ReplyError, ReplyOrIdError};
 use x11rb::protocol::xproto::{ConnectionExt, GrabMode, KeyButMask, Keycode, ModMask, Window};
+use x11rb::protocol::ErrorKind;
 use x11rb::rust_connection::RustConnection;
 use xkbcommon::xkb;
-use crate::wm::WM;
+use crate::action::Action;
+use crate::config::{Config, DEFAULT_MODE};
+use crate::wm::{XConnection, WM};
 
-static KEY_MAP: Mutex<Option<HashMap<String, u16>>> = Mutex::new(None);
+struct Keymap {
+    keycodes: HashMap<xkb::Keysym, u16>,
+    // the modifiers of Caps Lock, Num Lock and Scroll Lock, they do not change which binding a key press runs.
+    lock_mask: u16,
+    // the keys bound to any modifier.
+    modifier_keys: HashSet<Keycode>
+}
+
+thread_local! {
+    // per thread, WMs on different displays (like the ones of the tests) have different keyboards.
+    static KEY_MAP: RefCell<Option<Keymap>> = const { RefCell::new(None) };
+}
 
-pub fn init_keymap(conn: &RustConnection) -> Result<(), Box<dyn Error>> {
+// the keys whose modifier is ignored, Caps Lock is always LOCK.
+const LOCK_KEYS: &[xkb::Keysym] = &[xkb::KEY_Num_Lock, xkb::KEY_Scroll_Lock];
+
+// (re)reads the keysyms of every keycode, again whenever the keyboard mapping changes.
+pub fn init_keymap(conn: &impl Connection) -> Result<(), ReplyError> {
     let setup = conn.setup();
     let keyboard_mapping = conn.get_keyboard_mapping(
         setup.min_keycode, setup.max_keycode - setup.min_keycode + 1)?.reply()?;
 
-    let mut keymap = HashMap::new();
+    let mut keycodes = HashMap::new();
+    let mut lock_keycodes = Vec::new();
     let keysym_count = keyboard_mapping.keysyms_per_keycode as usize;
     for i in 0 .. keyboard_mapping.keysyms.len() / keysym_count {
         for j in 0 .. keyboard_mapping.keysyms_per_keycode {
             let keysym = keyboard_mapping.keysyms[j as usize + i * keysym_count];
             if keysym > 0 {
-                keymap.insert(xkb::keysym_get_name(keysym), (setup.min_keycode as u16) + (i as u16));
+                let keycode = (setup.min_keycode as u16) + (i as u16);
+                if LOCK_KEYS.contains(&keysym) {
+                    lock_keycodes.push(keycode as Keycode);
+                }
+                // a keysym on several keys is bound to the first one.
+                keycodes.entry(keysym).or_insert(keycode);
             }
         }
     }
-    *KEY_MAP.lock()? = Some(keymap);
+
+    // the modifier mapping lists the keycodes of each modifier from SHIFT to M5.
+    let modifier_mapping = conn.get_modifier_mapping()?.reply()?;
+    let per_modifier = (modifier_mapping.keycodes_per_modifier() as usize).max(1);
+    let mut lock_mask = u16::from(ModMask::LOCK);
+    for (i, keycode) in modifier_mapping.keycodes.iter().enumerate() {
+        if lock_keycodes.contains(keycode) {
+            lock_mask |= 1 << (i / per_modifier);
+        }
+    }
+    let modifier_keys = modifier_mapping.keycodes.iter().copied().filter(|k| *k != 0).collect();
+    KEY_MAP.with(|k| *k.borrow_mut() = Some(Keymap { keycodes, lock_mask, modifier_keys }));
     Ok(())
 }
 
-#[derive(Debug, Default, PartialEq, Eq, Hash)]
-struct KeyBind {
+pub(crate) fn lock_mask() -> u16 {
+    KEY_MAP.with(|k| k.borrow().as_ref().map_or(u16::from(ModMask::LOCK), |k| k.lock_mask))
+}
+
+fn is_modifier_key(key: Keycode) -> bool {
+    KEY_MAP.with(|k| k.borrow().as_ref().is_some_and(|k| k.modifier_keys.contains(&key)))
+}
+
+// every combination of the given modifiers, including none of them.
+pub(crate) fn combinations(mask: u16) -> impl Iterator<Item = u16> {
+    (0..=mask).filter(move |m| m & !mask == 0)
+}
+
+pub fn parse_modifier(name: &str) -> Option<ModMask> {
+    match name.to_ascii_lowercase().as_str() {
+        "shift"                  => Some(ModMask::SHIFT),
+        "lock"                   => Some(ModMask::LOCK),
+        "control" | "ctrl"       => Some(ModMask::CONTROL),
+        "m1" | "mod1" | "alt"    => Some(ModMask::M1),
+        "m2" | "mod2"            => Some(ModMask::M2),
+        "m3" | "mod3"            => Some(ModMask::M3),
+        "m4" | "mod4" | "super"  => Some(ModMask::M4),
+        "m5" | "mod5"            => Some(ModMask::M5),
+        "any"                    => Some(ModMask::ANY),
+        _                        => None
+    }
+}
+
+// key names are looked up as xkb spells them first, so that a and A stay different keys.
+fn parse_keysym(name: &str) -> Option<xkb::Keysym> {
+    [xkb::KEYSYM_NO_FLAGS, xkb::KEYSYM_CASE_INSENSITIVE].into_iter()
+        .map(|flags| xkb::keysym_from_name(name, flags))
+        .find(|keysym| *keysym != xkb::KEY_NoSymbol)
+}
+
+// why a binding from the config cannot be used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyBindError {
+    NoKey(String),
+    UnknownKey(String, String),
+    UnknownModifier(String, String),
+    Duplicate(String, String),
+    HiddenChord(String, String)
+}
+
+impl fmt::Display for KeyBindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyBindError::NoKey(bind) =>
+                write!(f, "'{}' has no key, expected something like M4+a", bind),
+            KeyBindError::UnknownKey(bind, key) =>
+                write!(f, "unknown key '{}' in '{}'", key, bind),
+            KeyBindError::UnknownModifier(bind, modifier) => write!(
+                f, "unknown modifier '{}' in '{}', expected Shift, Lock, Ctrl, Alt, Super, Mod1 to Mod5 or Any",
+                modifier, bind
+            ),
+            KeyBindError::Duplicate(first, second) =>
+                write!(f, "'{}' and '{}' are the same keys", first, second),
+            KeyBindError::HiddenChord(bind, chord) =>
+                write!(f, "'{}' is also the start of the chord '{}', which could never be typed", bind, chord)
+        }
+    }
+}
+
+impl std::error::Error for KeyBindError {}
+
+// the modifiers and the keysym of a key combination, which keycode it is depends on the keyboard layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyBind {
     mask: u16,
-    key: u16
+    keysym: xkb::Keysym
+}
+
+impl TryFrom<&str> for KeyBind {
+    type Error = KeyBindError;
+
+    // the last part is the key, everything before it are modifiers.
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let (modifiers, key) = value.rsplit_once('+').unwrap_or(("", value));
+        let mut mask = 0;
+        for m in modifiers.split('+').filter(|_| !modifiers.is_empty()) {
+            let modifier = parse_modifier(m)
+                .ok_or_else(|| KeyBindError::UnknownModifier(value.to_string(), m.to_string()))?;
+            mask |= u16::from(modifier);
+        }
+        if key.is_empty() || parse_modifier(key).is_some() {
+            return Err(KeyBindError::NoKey(value.to_string()));
+        }
+        let keysym = parse_keysym(key).ok_or_else(|| KeyBindError::UnknownKey(value.to_string(), key.to_string()))?;
+        Ok(KeyBind { mask, keysym })
+    }
 }
 
-impl From<&str> for KeyBind {
-    fn from(value: &str) -> Self {
-        let mut keybind = KeyBind::default();
-        let opt_keymap = KEY_MAP.lock().unwrap();
-        let keymap = opt_keymap.as_ref().unwrap();
-        for k in value.split("+") {
-            match &k[..] {
-                "SHIFT"   => { keybind.mask |= ModMask::SHIFT;   },
-                "LOCK"    => { keybind.mask |= ModMask::LOCK;    },
-                "CONTROL" => { keybind.mask |= ModMask::CONTROL; },
-                "M1"      => { keybind.mask |= ModMask::M1;      },
-                "M2"      => { keybind.mask |= ModMask::M2;      },
-                "M3"      => { keybind.mask |= ModMask::M3;      },
-                "M4"      => { keybind.mask |= ModMask::M4;      },
-                "M5"      => { keybind.mask |= ModMask::M5;      },
-                "ANY"     => { keybind.mask |= ModMask::ANY;     },
-                _         => { keybind.key = keymap[k]; }
+impl KeyBind {
+    // None if the key is not on the current keyboard.
+    fn resolve(&self, keymap: &Keymap) -> Option<KeyPress> {
+        keymap.keycodes.get(&self.keysym).map(|key| KeyPress { mask: self.mask, key: *key })
+    }
+}
+
+// a chord is a sequence of key combinations separated by spaces.
+pub fn parse_sequence(value: &str) -> Result<Vec<KeyBind>, KeyBindError> {
+    let sequence = value.split_whitespace()
+        .map(KeyBind::try_from)
+        .collect::<Result<Vec<KeyBind>, KeyBindError>>()?;
+    if sequence.is_empty() {
+        return Err(KeyBindError::NoKey(value.to_string()));
+    }
+    Ok(sequence)
+}
+
+// every binding of a mode has to be reachable, so no two of them may use the same keys and none may
+// be the start of a chord.
+pub fn check_bindings<'a>(names: impl IntoIterator<Item = &'a str>) -> Result<(), KeyBindError> {
+    let mut sequences = names.into_iter()
+        .map(|name| Ok((name, parse_sequence(name)?)))
+        .collect::<Result<Vec<(&str, Vec<KeyBind>)>, KeyBindError>>()?;
+    sequences.sort_by_key(|(name, _)| *name);
+    for (i, (a, sequence_a)) in sequences.iter().enumerate() {
+        for (b, sequence_b) in &sequences[i + 1..] {
+            if sequence_a == sequence_b {
+                return Err(KeyBindError::Duplicate(a.to_string(), b.to_string()));
+            } else if sequence_b.starts_with(sequence_a) {
+                return Err(KeyBindError::HiddenChord(a.to_string(), b.to_string()));
+            } else if sequence_a.starts_with(sequence_b) {
+                return Err(KeyBindError::HiddenChord(b.to_string(), a.to_string()));
             }
         }
-        keybind
     }
+    Ok(())
+}
+
+// a key combination on the current keyboard, as key press events report it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct KeyPress {
+    mask: u16,
+    key: u16
 }
 
-type KeyBindAction = Box<dyn Fn(&mut WM) -> Result<(), ReplyOrIdError>>;
+pub type KeyBindAction<C = RustConnection> = Box<dyn Fn(&mut WM<C>) -> Result<(), ReplyOrIdError>>;
 
-pub fn make_action<F>(f: F) -> KeyBindAction where
-    F: Fn(&mut WM) -> Result<(), ReplyOrIdError> + 'static
+pub fn make_action<C, F>(f: F) -> KeyBindAction<C> where
+    C: XConnection,
+    F: Fn(&mut WM<C>) -> Result<(), ReplyOrIdError> + 'static
 {
-    Box::new(f) as KeyBindAction
+    Box::new(f) as KeyBindAction<C>
 }
 
-pub trait KeyHandler {
-    fn grab_keys(&self, conn: &RustConnection, win: Window) -> Result<(), ReplyError>;
-    fn handle_key_bind(&self, wm: &mut WM, mask: KeyButMask, key: Keycode) -> Result<(), ReplyOrIdError>;
+pub trait KeyHandler<C: XConnection> {
+    fn grab_keys(&self, conn: &C, win: Window) -> Result<(), ReplyError>;
+    // called after the keyboard mapping changed, the keys are grabbed again afterwards.
+    fn update_keymap(&self, conn: &C) -> Result<(), ReplyError>;
+    fn handle_key_bind(&self, wm: &mut WM<C>, mask: KeyButMask, key: Keycode) -> Result<(), ReplyOrIdError>;
+    // when a half typed chord is abandoned, handle_timeout is called once it passed.
+    fn chord_deadline(&self) -> Option<Instant>;
+    fn handle_timeout(&self, wm: &mut WM<C>) -> Result<(), ReplyError>;
 }
 
-pub struct KeyBindHandler where {
-    bind_map: HashMap<KeyBind, KeyBindAction>
+// the bindings of a mode by name and by the keys that run them, a chord has several keys.
+struct Mode<C: XConnection> {
+    binds: Vec<(String, Vec<KeyBind>, KeyBindAction<C>)>,
+    sequences: RefCell<HashMap<Vec<KeyPress>, usize>>
 }
 
-impl KeyBindHandler where {
-    pub fn new(map: HashMap<&str, KeyBindAction>) -> Self {
-        let mut bind_map = HashMap::with_capacity(map.len());
-        for (k, v) in map {
-            bind_map.insert(KeyBind::from(k), v);
+impl<C: XConnection> Mode<C> {
+    fn new(map: HashMap<&str, KeyBindAction<C>>) -> Result<Self, KeyBindError> {
+        check_bindings(map.keys().copied())?;
+        let mut binds = map.into_iter()
+            .map(|(name, action)| Ok((name.to_owned(), parse_sequence(name)?, action)))
+            .collect::<Result<Vec<(String, Vec<KeyBind>, KeyBindAction<C>)>, KeyBindError>>()?;
+        // the warnings about unusable bindings come in the same order every time.
+        binds.sort_by(|a, b| a.0.cmp(&b.0));
+        let sequences = RefCell::new(Self::resolve(&binds));
+        Ok(Self { binds, sequences })
+    }
+
+    // looks up the keycodes of every binding in the current keyboard mapping.
+    fn resolve(binds: &[(String, Vec<KeyBind>, KeyBindAction<C>)]) -> HashMap<Vec<KeyPress>, usize> {
+        KEY_MAP.with(|keymap| {
+            let keymap = keymap.borrow();
+            let keymap = keymap.as_ref().expect("init_keymap has to be called first");
+            let mut sequences: HashMap<Vec<KeyPress>, usize> = HashMap::with_capacity(binds.len());
+            for (i, (name, sequence, _)) in binds.iter().enumerate() {
+                let Some(keys) = sequence.iter().map(|k| k.resolve(keymap)).collect::<Option<Vec<KeyPress>>>() else {
+                    warn!("the keys of binding '{}' are not on the keyboard", name);
+                    continue;
+                };
+                // different keysyms can be on the same key, like a and A.
+                let clash = sequences.iter().find(|(other, _)| other.starts_with(&keys) || keys.starts_with(other));
+                if let Some((_, j)) = clash {
+                    warn!("'{}' uses the same keys as '{}' on this keyboard, ignoring it", name, binds[*j].0);
+                    continue;
+                }
+                sequences.insert(keys, i);
+            }
+            sequences
+        })
+    }
+}
+
+// the bindings are kept by name, the keycodes they map to change with the keyboard layout.
+// only the first keys of the default mode are grabbed, the WM grabs the whole keyboard while
+// another mode is active or a chord is half typed.
+pub struct KeyBindHandler<C = RustConnection> where C: XConnection {
+    modes: HashMap<String, Mode<C>>,
+    chord_timeout: Duration,
+    pending: RefCell<Vec<KeyPress>>,
+    deadline: Cell<Option<Instant>>
+}
+
+impl<C> KeyBindHandler<C> where C: XConnection {
+    pub fn new(map: HashMap<&str, KeyBindAction<C>>) -> Result<Self, KeyBindError> {
+        Ok(Self {
+            modes: HashMap::from([(DEFAULT_MODE.to_owned(), Mode::new(map)?)]),
+            chord_timeout: Duration::from_millis(Config::default().chord_timeout_ms),
+            pending: RefCell::new(Vec::new()),
+            deadline: Cell::new(None)
+        })
+    }
+
+    // the bindings of a loaded config have been checked already.
+    pub fn from_config(config: &Config) -> Result<Self, KeyBindError> {
+        fn actions<C: XConnection>(binds: &HashMap<String, Action>) -> HashMap<&str, KeyBindAction<C>> {
+            binds.iter().map(|(k, a)| (k.as_str(), a.clone().into_key_bind_action())).collect()
         }
-        Self { bind_map }
+        let mut handler = Self::new(actions(&config.keybinds))?;
+        for (name, binds) in &config.modes {
+            handler.add_mode(name, actions(binds))?;
+        }
+        // capped so that adding it to the current time cannot overflow.
+        handler.chord_timeout = Duration::from_millis(config.chord_timeout_ms.min(u32::MAX as u64));
+        Ok(handler)
+    }
+
+    // Escape returns to the default mode unless the mode binds it.
+    pub fn add_mode(&mut self, name: &str, mut map: HashMap<&str, KeyBindAction<C>>) -> Result<(), KeyBindError> {
+        let escape = KeyBind::try_from("Escape")?;
+        if !map.keys().any(|k| parse_sequence(k).is_ok_and(|sequence| sequence[0] == escape)) {
+            map.insert("Escape", make_action(|wm| { wm.set_key_mode(DEFAULT_MODE)?; Ok(()) }));
+        }
+        self.modes.insert(name.to_owned(), Mode::new(map)?);
+        Ok(())
+    }
+
+    fn cancel_chord(&self, wm: &mut WM<C>) -> Result<(), ReplyError> {
+        self.pending.take();
+        self.deadline.set(None);
+        wm.set_key_chord(None)?;
+        Ok(())
     }
 }
 
-impl KeyHandler for KeyBindHandler {
-    fn grab_keys(&self, conn: &RustConnection, win: Window) -> Result<(), ReplyError> {
-        for (k, _) in &self.bind_map {
-            conn.grab_key(
-                false, win,
-                ModMask::from(k.mask),
-                k.key as u8,
-                GrabMode::ASYNC,
-                GrabMode::ASYNC
-            )?;
+impl<C: XConnection> KeyHandler<C> for KeyBindHandler<C> {
+    fn grab_keys(&self, conn: &C, win: Window) -> Result<(), ReplyError> {
+        let Some(mode) = self.modes.get(DEFAULT_MODE) else { return Ok(()) };
+        // the first key of every binding and the name of a binding starting with it.
+        let first_keys: HashMap<KeyPress, usize> = mode.sequences.borrow().iter().map(|(s, i)| (s[0], *i)).collect();
+        // the key has to be grabbed once for every state of the lock keys.
+        let locks = lock_mask();
+        let mut cookies = Vec::new();
+        for (k, i) in first_keys {
+            for lock in combinations(locks & !k.mask) {
+                let cookie = conn.grab_key(
+                    false, win,
+                    ModMask::from(k.mask | lock),
+                    k.key as u8,
+                    GrabMode::ASYNC,
+                    GrabMode::ASYNC
+                )?;
+                cookies.push((cookie, i));
+            }
+        }
+        // other clients like hotkey daemons can own keys on the root window, the grabs are checked
+        // after sending all of them to wait only once.
+        if !conn.setup().roots.iter().any(|screen| screen.root == win) {
+            return Ok(());
+        }
+        let mut owned = BTreeSet::new();
+        for (cookie, i) in cookies {
+            match cookie.check() {
+                Err(ReplyError::X11Error(err)) if err.error_kind == ErrorKind::Access => { owned.insert(i); },
+                result => result?
+            }
+        }
+        for i in owned {
+            warn!("'{}' could not be grabbed, another client owns its keys", mode.binds[i].0);
         }
         Ok(())
     }
 
-    fn handle_key_bind(&self, wm: &mut WM, mask: KeyButMask, key: Keycode) -> Result<(), ReplyOrIdError> {
-        let pressed = KeyBind { mask: mask.into(), key: key as u16 };
-        self.bind_map.get(&pressed).map(|f| f(wm));
+    fn update_keymap(&self, conn: &C) -> Result<(), ReplyError> {
+        init_keymap(conn)?;
+        for mode in self.modes.values() {
+            *mode.sequences.borrow_mut() = Mode::resolve(&mode.binds);
+        }
         Ok(())
     }
+
+    fn handle_key_bind(&self, wm: &mut WM<C>, mask: KeyButMask, key: Keycode) -> Result<(), ReplyOrIdError> {
+        // the state also holds the pressed mouse buttons above the modifiers.
+        let modifiers = u16::from(mask) & 0xff & !lock_mask();
+        let pressed = KeyPress { mask: modifiers, key: key as u16 };
+        let mode_name = wm.key_mode().unwrap_or(DEFAULT_MODE);
+        let Some(mode) = self.modes.get(mode_name).or_else(|| self.modes.get(DEFAULT_MODE)) else { return Ok(()) };
+        let mut keys = self.pending.take();
+        keys.push(pressed);
+
+        // the sequences are not borrowed while the action runs.
+        let bind = mode.sequences.borrow().get(&keys).copied();
+        let prefix_of = mode.sequences.borrow().iter()
+            .find(|(sequence, _)| sequence.len() > keys.len() && sequence.starts_with(&keys))
+            .map(|(_, i)| *i);
+        if let Some(i) = bind {
+            debug!("key {} with modifiers {:#x} runs '{}'", key, pressed.mask, mode.binds[i].0);
+            self.cancel_chord(wm)?;
+            (mode.binds[i].2)(wm)
+        } else if let Some(i) = prefix_of {
+            // the top bar shows the part of the chord typed so far.
+            let typed: Vec<&str> = mode.binds[i].0.split_whitespace().take(keys.len()).collect();
+            debug!("key {} with modifiers {:#x} starts the chord '{}'", key, pressed.mask, mode.binds[i].0);
+            // the rest of the chord only arrives with the keyboard grabbed.
+            if wm.set_key_chord(Some(typed.join(" ")))? {
+                self.pending.replace(keys);
+                self.deadline.set(Some(Instant::now() + self.chord_timeout));
+            } else {
+                self.cancel_chord(wm)?;
+            }
+            Ok(())
+        } else if is_modifier_key(key) {
+            // pressing the modifiers of the next key of a chord does not abandon it.
+            keys.pop();
+            self.pending.replace(keys);
+            Ok(())
+        } else {
+            debug!("key {} with modifiers {:#x} is not bound", key, pressed.mask);
+            Ok(self.cancel_chord(wm)?)
+        }
+    }
+
+    fn chord_deadline(&self) -> Option<Instant> {
+        self.deadline.get()
+    }
+
+    fn handle_timeout(&self, wm: &mut WM<C>) -> Result<(), ReplyError> {
+        match self.deadline.get() {
+            Some(deadline) if deadline <= Instant::now() => {
+                debug!("the chord timed out");
+                self.cancel_chord(wm)
+            },
+            _ => Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_keybindings_are_rejected_when_loading() {
+        let load = |keybinds: &str| toml::from_str::<Config>(&format!("[keybinds]\n{}", keybinds)).map(|_| ()).map_err(|e| e.to_string());
+
+        assert!(load(r#""M4+a" = "stack_inc""#).is_ok());
+        assert!(load(r#""M4+nokey" = "stack_inc""#).unwrap_err().contains("unknown key 'nokey' in 'M4+nokey'"));
+        assert!(load(r#""Hyper+a" = "stack_inc""#).unwrap_err().contains("unknown modifier 'Hyper'"));
+        assert!(load(r#""M4+SHIFT" = "stack_inc""#).unwrap_err().contains("'M4+SHIFT' has no key"));
+        assert!(load(r#""M4+" = "stack_inc""#).unwrap_err().contains("has no key"));
+        assert!(load("\"M4+a\" = \"stack_inc\"\n\"super+a\" = \"stack_dec\"")
+            .unwrap_err().contains("'M4+a' and 'super+a' are the same keys"));
+        assert!(load("\"M4+o\" = \"stack_inc\"\n\"M4+o x\" = \"stack_dec\"")
+            .unwrap_err().contains("'M4+o' is also the start of the chord 'M4+o x'"));
+        assert!(toml::from_str::<Config>("[modes.resize]\n\"h\" = \"stack_inc\"\n\"H\" = \"stack_inc\"").is_ok());
+        assert!(toml::from_str::<Config>("[modes.default]\n\"h\" = \"stack_inc\"").is_err());
+    }
+
+    #[test]
+    fn modifier_aliases_are_case_insensitive() {
+        assert_eq!(KeyBind::try_from("super+Ctrl+ALT+return"), KeyBind::try_from("M4+CONTROL+M1+Return"));
+        assert_eq!(KeyBind::try_from("Mod4+shift+a"), KeyBind::try_from("M4+SHIFT+a"));
+        // only the case of modifiers is ignored, a and A are different keys.
+        assert_ne!(KeyBind::try_from("M4+A"), KeyBind::try_from("M4+a"));
+    }
 }
@@ -1,17 +1,77 @@
 use x11rb::protocol::xproto::{Rectangle, Window};
-use crate::config::TOP_BAR_HEIGHT;
+
+// the per-workspace settings a layout may take into account.
+pub struct LayoutParams {
+    pub master_ratio: f32,
+    pub master_count: usize,
+    pub min_width: u16
+}
 
 pub trait WindowLayout {
-    fn layout(&self, screen: Rectangle, windows: &Vec<Window>) -> Vec<(Window, Rectangle)>;
+    fn layout(&self, screen: Rectangle, windows: &[Window], params: &LayoutParams) -> Vec<(Window, Rectangle)>;
+
+    // layouts whose tiles overlap have the focused window raised above the others.
+    fn stacks_windows(&self) -> bool {
+        false
+    }
+}
+
+// the layouts selectable by name, in the order they are cycled through.
+pub struct LayoutRegistry {
+    layouts: Vec<(String, Box<dyn WindowLayout>)>
+}
+
+impl LayoutRegistry {
+    pub fn empty() -> Self {
+        Self { layouts: Vec::new() }
+    }
+
+    // a layout registered under an existing name replaces the old one.
+    pub fn register(&mut self, name: &str, layout: impl WindowLayout + 'static) {
+        match self.layouts.iter_mut().find(|(n, _)| n == name) {
+            Some(entry) => entry.1 = Box::new(layout),
+            None => self.layouts.push((name.to_string(), Box::new(layout)))
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn WindowLayout> {
+        self.layouts.iter().find(|(n, _)| n == name).map(|(_, layout)| layout.as_ref())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.layouts.iter().map(|(n, _)| n.as_str())
+    }
+
+    // the name delta steps away from name, wrapping around at both ends.
+    pub fn cycle(&self, name: &str, delta: i32) -> Option<&str> {
+        let len = self.layouts.len() as i32;
+        let index = self.layouts.iter().position(|(n, _)| n == name).unwrap_or(0) as i32;
+        self.layouts.get((index + delta).rem_euclid(len.max(1)) as usize).map(|(n, _)| n.as_str())
+    }
+}
+
+impl Default for LayoutRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register("tree", TreeLayout);
+        registry.register("fibonacci", FibonacciLayout);
+        registry.register("master", MasterStackLayout);
+        registry.register("monocle", MonocleLayout);
+        registry
+    }
 }
 
 pub struct FibonacciLayout;
 
 impl WindowLayout for FibonacciLayout {
-    fn layout(&self, screen: Rectangle, windows: &Vec<Window>) -> Vec<(Window, Rectangle)> {
+    fn layout(&self, screen: Rectangle, windows: &[Window], _: &LayoutParams) -> Vec<(Window, Rectangle)> {
         let mut result = Vec::with_capacity(windows.len());
-        if windows.len() > 0 {
-            let (mut x, mut y, mut width, mut height) = (0i16, TOP_BAR_HEIGHT as i16, screen.width, screen.height - TOP_BAR_HEIGHT);
+        if !windows.is_empty() {
+            let (mut x, mut y, mut width, mut height) = (screen.x, screen.y, screen.width, screen.height);
             for i in 1..windows.len() {
                 if i % 2 == 0 {
                     height /= 2;
@@ -35,15 +95,15 @@ impl WindowLayout for FibonacciLayout {
 pub struct TreeLayout;
 
 impl WindowLayout for TreeLayout {
-    fn layout(&self, screen: Rectangle, windows: &Vec<Window>) -> Vec<(Window, Rectangle)> {
+    fn layout(&self, screen: Rectangle, windows: &[Window], _: &LayoutParams) -> Vec<(Window, Rectangle)> {
         let mut result = Vec::with_capacity(windows.len());
-        if windows.len() > 0 {
+        if !windows.is_empty() {
             let mut nr_leafs = 0;
             let mut leafs: Vec<(Window, Rectangle, bool)> = Vec::with_capacity(windows.len() * 2);
-            leafs.push((windows[0], Rectangle { x: 0, y: TOP_BAR_HEIGHT as i16, width: screen.width, height: screen.height - TOP_BAR_HEIGHT }, true));
+            leafs.push((windows[0], screen, true));
 
             while nr_leafs < windows.len() - 1 {
-                let mut root: &(Window, Rectangle, bool) = &leafs[nr_leafs].clone();
+                let root: &(Window, Rectangle, bool) = &leafs[nr_leafs].clone();
                 if root.2 {
                     leafs.push((
                         root.0,
@@ -98,3 +158,128 @@ impl WindowLayout for TreeLayout {
         result
     }
 }
+
+// the first master_count windows share a column on the left, the others are stacked on the right.
+pub struct MasterStackLayout;
+
+impl MasterStackLayout {
+    fn column(x: i16, y: i16, width: u16, height: u16, windows: &[Window]) -> Vec<(Window, Rectangle)> {
+        let mut result = Vec::with_capacity(windows.len());
+        let mut offset = 0;
+        for (i, window) in windows.iter().enumerate() {
+            // the last window takes up the pixels lost to rounding.
+            let h = if i == windows.len() - 1 { height - offset } else { height / windows.len() as u16 };
+            result.push((*window, Rectangle { x, y: y + offset as i16, width, height: h }));
+            offset += h;
+        }
+        result
+    }
+}
+
+impl WindowLayout for MasterStackLayout {
+    fn layout(&self, screen: Rectangle, windows: &[Window], params: &LayoutParams) -> Vec<(Window, Rectangle)> {
+        let masters = params.master_count.min(windows.len());
+        if masters == 0 || masters == windows.len() {
+            return Self::column(screen.x, screen.y, screen.width, screen.height, windows);
+        }
+
+        let max_width = screen.width.saturating_sub(params.min_width).max(params.min_width.min(screen.width));
+        let master_width = ((screen.width as f32 * params.master_ratio) as u16)
+            .clamp(params.min_width.min(max_width), max_width);
+        let mut result = Self::column(screen.x, screen.y, master_width, screen.height, &windows[..masters]);
+        result.extend(Self::column(
+            screen.x + master_width as i16, screen.y,
+            screen.width - master_width, screen.height,
+            &windows[masters..]
+        ));
+        result
+    }
+}
+
+// every window covers the whole area, only the topmost one is visible.
+pub struct MonocleLayout;
+
+impl WindowLayout for MonocleLayout {
+    fn layout(&self, screen: Rectangle, windows: &[Window], _: &LayoutParams) -> Vec<(Window, Rectangle)> {
+        windows.iter().map(|w| (*w, screen)).collect()
+    }
+
+    fn stacks_windows(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(registry: &LayoutRegistry) -> Vec<&str> {
+        registry.names().collect()
+    }
+
+    fn rect(x: i16, y: i16, width: u16, height: u16) -> Rectangle {
+        Rectangle { x, y, width, height }
+    }
+
+    fn master_stack(windows: &[Window], master_ratio: f32, master_count: usize, min_width: u16) -> Vec<Rectangle> {
+        let params = LayoutParams { master_ratio, master_count, min_width };
+        MasterStackLayout.layout(rect(10, 20, 1000, 601), windows, &params).into_iter().map(|(_, r)| r).collect()
+    }
+
+    #[test]
+    fn master_stack_splits_masters_from_the_stack() {
+        assert_eq!(master_stack(&[1, 2, 3], 0.6, 1, 10), vec![
+            rect(10, 20, 600, 601),
+            rect(610, 20, 400, 300),
+            rect(610, 320, 400, 301)
+        ]);
+        assert_eq!(master_stack(&[1, 2, 3], 0.5, 2, 10), vec![
+            rect(10, 20, 500, 300),
+            rect(10, 320, 500, 301),
+            rect(510, 20, 500, 601)
+        ]);
+    }
+
+    #[test]
+    fn master_stack_uses_a_single_column_without_a_stack_or_masters() {
+        let column = vec![rect(10, 20, 1000, 300), rect(10, 320, 1000, 301)];
+        assert_eq!(master_stack(&[1, 2], 0.6, 0, 10), column);
+        assert_eq!(master_stack(&[1, 2], 0.6, 2, 10), column);
+        assert_eq!(master_stack(&[1, 2], 0.6, 5, 10), column);
+        assert!(master_stack(&[], 0.6, 1, 10).is_empty());
+    }
+
+    #[test]
+    fn master_stack_keeps_both_columns_wider_than_min_width() {
+        assert_eq!(master_stack(&[1, 2], 0.0, 1, 100)[0].width, 100);
+        assert_eq!(master_stack(&[1, 2], 1.0, 1, 100)[1].width, 100);
+        // a min_width above half the screen cannot be met by both columns, the stack gets the rest.
+        assert_eq!(master_stack(&[1, 2], 0.5, 1, 700).iter().map(|r| r.width).collect::<Vec<_>>(), vec![700, 300]);
+    }
+
+    #[test]
+    fn registering_an_existing_name_replaces_the_layout_in_place() {
+        let mut registry = LayoutRegistry::default();
+        assert!(!registry.get("tree").unwrap().stacks_windows());
+        registry.register("tree", MonocleLayout);
+        assert_eq!(names(&registry), vec!["tree", "fibonacci", "master", "monocle"]);
+        assert!(registry.get("tree").unwrap().stacks_windows());
+    }
+
+    #[test]
+    fn cycling_wraps_around_at_both_ends() {
+        let registry = LayoutRegistry::default();
+        assert_eq!(registry.cycle("tree", 1), Some("fibonacci"));
+        assert_eq!(registry.cycle("monocle", 1), Some("tree"));
+        assert_eq!(registry.cycle("tree", -1), Some("monocle"));
+        assert_eq!(registry.cycle("master", 6), Some("tree"));
+    }
+
+    #[test]
+    fn cycling_from_an_unknown_layout_starts_at_the_first_one() {
+        let registry = LayoutRegistry::default();
+        assert_eq!(registry.cycle("spiral", 0), Some("tree"));
+        assert_eq!(registry.cycle("spiral", 1), Some("fibonacci"));
+        assert_eq!(LayoutRegistry::empty().cycle("tree", 1), None);
+    }
+}
@@ -0,0 +1,18 @@
+pub mod wm;
+pub mod action;
+mod atoms;
+pub mod config;
+pub mod error;
+pub mod ipc;
+pub mod layout;
+pub mod logging;
+mod monitor;
+pub mod keybind;
+pub mod mousebind;
+pub mod rules;
+mod size_hints;
+pub mod socket_path;
+mod workspace;
+
+#[cfg(test)]
+mod fake_connection;
@@ -0,0 +1,220 @@
+use std::fs::{self, File};
+use std::io::{IoSlice, Write};
+use std::os::unix::io::RawFd;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, mem};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use x11rb::connection::{
+    Connection, DiscardMode, RawEventAndSeqNumber, ReplyOrError, RequestConnection, RequestKind, SequenceNumber
+};
+use x11rb::cookie::{Cookie, CookieWithFds, VoidCookie};
+use x11rb::errors::{ConnectionError, ParseError, ReplyOrIdError};
+use x11rb::protocol::xproto::Setup;
+use x11rb::protocol::{Event, Request};
+use x11rb::utils::RawFdContainer;
+use x11rb::x11_utils::{
+    parse_request_header, BigRequests, ExtInfoProvider, ExtensionInformation, TryParse, TryParseFd, X11Error
+};
+
+use crate::wm::XConnection;
+
+// the target of the messages that dump X traffic, they are only written at the trace level.
+pub const X11_TARGET: &str = "mswm::x11";
+
+// writes everything to the log file, warnings and errors also go to stderr.
+struct Logger {
+    level: LevelFilter,
+    file: Option<Mutex<File>>
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= self.level && metadata.target().starts_with("mswm")
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let line = format!("{}.{:03} {:5} {}: {}\n",
+            time.as_secs(), time.subsec_millis(), record.level(), record.target(), record.args());
+        let written = self.file.as_ref()
+            .is_some_and(|file| file.lock().is_ok_and(|mut file| file.write_all(line.as_bytes()).is_ok()));
+        if !written || record.level() <= Level::Warn {
+            eprintln!("mswm: {}", record.args());
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(mut file) = self.file.as_ref().and_then(|file| file.lock().ok()) {
+            let _ = file.flush();
+        }
+    }
+}
+
+// $XDG_STATE_HOME/mswm/mswm.log, the log of the previous session is kept as mswm.log.old.
+pub fn log_path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_STATE_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".local").join("state")))?;
+    Some(dir.join("mswm").join("mswm.log"))
+}
+
+// without a usable log file everything is written to stderr.
+pub fn init(level: LevelFilter) {
+    let file = log_path().and_then(|path| {
+        fs::create_dir_all(path.parent()?).ok()?;
+        let _ = fs::rename(&path, path.with_extension("log.old"));
+        File::create(path).ok()
+    });
+    let logger = Box::leak(Box::new(Logger { level, file: file.map(Mutex::new) }));
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+// parses the request the same way the server would, to log it.
+fn describe_request(bufs: &[IoSlice<'_>]) -> String {
+    struct NoExtensions;
+    impl ExtInfoProvider for NoExtensions {
+        fn get_from_major_opcode(&self, _: u8) -> Option<(&str, ExtensionInformation)> { None }
+        fn get_from_event_code(&self, _: u8) -> Option<(&str, ExtensionInformation)> { None }
+        fn get_from_error_code(&self, _: u8) -> Option<(&str, ExtensionInformation)> { None }
+    }
+    let bytes: Vec<u8> = bufs.iter().flat_map(|b| b.iter().copied()).collect();
+    match parse_request_header(&bytes, BigRequests::NotEnabled) {
+        Ok((header, body)) => match Request::parse(header, body, &mut Vec::new(), &NoExtensions) {
+            Ok(Request::Unknown(..)) | Err(_) => format!("request {}.{}", header.major_opcode, header.minor_opcode),
+            Ok(request) => format!("{:?}", request)
+        },
+        Err(_) => format!("request of {} bytes", bytes.len())
+    }
+}
+
+// passes everything on to the wrapped connection and logs the requests sent and the events received
+// at the trace level.
+pub struct TracingConnection<C>(pub(crate) C);
+
+impl<C: Connection> TracingConnection<C> {
+    pub fn new(conn: C) -> Self {
+        Self(conn)
+    }
+
+    fn trace_request(&self, bufs: &[IoSlice<'_>], sequence: SequenceNumber) {
+        if log::log_enabled!(target: X11_TARGET, Level::Trace) {
+            log::trace!(target: X11_TARGET, "-> #{} {}", sequence, describe_request(bufs));
+        }
+    }
+}
+
+impl<C: Connection> RequestConnection for TracingConnection<C> {
+    type Buf = C::Buf;
+
+    // the cookies of the wrapped connection are forgotten instead of dropped, which would discard
+    // the reply. the cookie handed out takes over their sequence number.
+    fn send_request_with_reply<R: TryParse>(&self, bufs: &[IoSlice<'_>], fds: Vec<RawFdContainer>)
+        -> Result<Cookie<'_, Self, R>, ConnectionError> {
+        let cookie = self.0.send_request_with_reply::<R>(bufs, fds)?;
+        let sequence = cookie.sequence_number();
+        mem::forget(cookie);
+        self.trace_request(bufs, sequence);
+        Ok(Cookie::new(self, sequence))
+    }
+
+    fn send_request_with_reply_with_fds<R: TryParseFd>(&self, bufs: &[IoSlice<'_>], fds: Vec<RawFdContainer>)
+        -> Result<CookieWithFds<'_, Self, R>, ConnectionError> {
+        let cookie = self.0.send_request_with_reply_with_fds::<R>(bufs, fds)?;
+        let sequence = cookie.sequence_number();
+        mem::forget(cookie);
+        self.trace_request(bufs, sequence);
+        Ok(CookieWithFds::new(self, sequence))
+    }
+
+    fn send_request_without_reply(&self, bufs: &[IoSlice<'_>], fds: Vec<RawFdContainer>)
+        -> Result<VoidCookie<'_, Self>, ConnectionError> {
+        let cookie = self.0.send_request_without_reply(bufs, fds)?;
+        let sequence = cookie.sequence_number();
+        mem::forget(cookie);
+        self.trace_request(bufs, sequence);
+        Ok(VoidCookie::new(self, sequence))
+    }
+
+    fn discard_reply(&self, sequence: SequenceNumber, kind: RequestKind, mode: DiscardMode) {
+        self.0.discard_reply(sequence, kind, mode)
+    }
+
+    fn prefetch_extension_information(&self, extension_name: &'static str) -> Result<(), ConnectionError> {
+        self.0.prefetch_extension_information(extension_name)
+    }
+
+    fn extension_information(&self, extension_name: &'static str) -> Result<Option<ExtensionInformation>, ConnectionError> {
+        self.0.extension_information(extension_name)
+    }
+
+    fn wait_for_reply_or_raw_error(&self, sequence: SequenceNumber) -> Result<ReplyOrError<C::Buf>, ConnectionError> {
+        self.0.wait_for_reply_or_raw_error(sequence)
+    }
+
+    fn wait_for_reply(&self, sequence: SequenceNumber) -> Result<Option<C::Buf>, ConnectionError> {
+        self.0.wait_for_reply(sequence)
+    }
+
+    fn wait_for_reply_with_fds_raw(&self, sequence: SequenceNumber)
+        -> Result<ReplyOrError<(C::Buf, Vec<RawFdContainer>), C::Buf>, ConnectionError> {
+        self.0.wait_for_reply_with_fds_raw(sequence)
+    }
+
+    fn check_for_raw_error(&self, sequence: SequenceNumber) -> Result<Option<C::Buf>, ConnectionError> {
+        self.0.check_for_raw_error(sequence)
+    }
+
+    fn prefetch_maximum_request_bytes(&self) {
+        self.0.prefetch_maximum_request_bytes()
+    }
+
+    fn maximum_request_bytes(&self) -> usize {
+        self.0.maximum_request_bytes()
+    }
+
+    fn parse_error(&self, error: &[u8]) -> Result<X11Error, ParseError> {
+        self.0.parse_error(error)
+    }
+
+    fn parse_event(&self, event: &[u8]) -> Result<Event, ParseError> {
+        let event = self.0.parse_event(event)?;
+        log::trace!(target: X11_TARGET, "<- {:?}", event);
+        Ok(event)
+    }
+}
+
+impl<C: Connection> Connection for TracingConnection<C> {
+    fn wait_for_raw_event_with_sequence(&self) -> Result<RawEventAndSeqNumber<C::Buf>, ConnectionError> {
+        self.0.wait_for_raw_event_with_sequence()
+    }
+
+    fn poll_for_raw_event_with_sequence(&self) -> Result<Option<RawEventAndSeqNumber<C::Buf>>, ConnectionError> {
+        self.0.poll_for_raw_event_with_sequence()
+    }
+
+    fn flush(&self) -> Result<(), ConnectionError> {
+        self.0.flush()
+    }
+
+    fn setup(&self) -> &Setup {
+        self.0.setup()
+    }
+
+    fn generate_id(&self) -> Result<u32, ReplyOrIdError> {
+        self.0.generate_id()
+    }
+}
+
+impl<C: XConnection> XConnection for TracingConnection<C> {
+    fn raw_fd(&self) -> RawFd {
+        self.0.raw_fd()
+    }
+}
@@ -1,40 +1,101 @@
-mod wm;
-mod config;
-mod layout;
-mod keybind;
-
-use std::collections::HashMap;
-use crate::wm::WM;
-
+use std::{env, process};
+use log::{error, info, warn, LevelFilter};
 use x11rb::errors::ReplyOrIdError;
 use x11rb::protocol::ErrorKind;
-use crate::config::spawn_program;
-use crate::keybind::{init_keymap, KeyBindHandler, make_action};
+use mswm::config::{Config, ConfigWatcher};
+use mswm::error::{recover, Error};
+use mswm::ipc::IpcServer;
+use mswm::keybind::{init_keymap, KeyBindHandler};
+use mswm::layout::LayoutRegistry;
+use mswm::logging::{self, TracingConnection};
+use mswm::socket_path::socket_path;
+use mswm::wm::WM;
+
+const USAGE: &str = "usage: mswm [--log-level off|error|warn|info|debug|trace]";
 
 fn main() {
+    logging::init(log_level());
+    info!("starting mswm {}", env!("CARGO_PKG_VERSION"));
+
     let (conn, screen_num) = x11rb::connect(None)
-        .expect("Failed to connect to the X11 server");
-    init_keymap(&conn).unwrap();
-
-    let key_handler = KeyBindHandler::new(HashMap::from([
-        ("M4+f",            make_action(|wm| { wm.layout_flag = true;   wm.apply_layout() })),
-        ("M4+g",            make_action(|wm| { wm.layout_flag = false;  wm.apply_layout() })),
-        ("M4+j",            make_action(|wm| wm.stack_inc())),
-        ("M4+k",            make_action(|wm| wm.stack_dec())),
-        ("M4+SHIFT+c",      make_action(|wm| wm.kill_focused())),
-        ("M4+SHIFT+Return", make_action(|_|  spawn_program("xterm")))
-    ]));
-
-    let wm = WM::create_wm(conn, screen_num, &key_handler);
-    if let Err(ReplyOrIdError::X11Error(error)) = wm {
-        if error.error_kind == ErrorKind::Access {
-            panic!("There is already a window manager present");
-        } else {
-            panic!("An error occurred while trying to become wm");
+        .unwrap_or_else(|err| fail(format!("failed to connect to the X server: {}", err)));
+    if let Err(err) = init_keymap(&conn) {
+        fail(format!("failed to read the keyboard mapping: {}", err));
+    }
+    let conn = TracingConnection::new(conn);
+
+    // additional layouts can be added here with layouts.register(name, layout).
+    let layouts = LayoutRegistry::default();
+
+    let config = Config::load(&layouts).unwrap_or_else(|err| {
+        warn!("failed to load config, using defaults: {}", err);
+        Config::default()
+    });
+
+    let mut key_handler = KeyBindHandler::from_config(&config)
+        .unwrap_or_else(|err| fail(format!("invalid keybinding: {}", err)));
+
+    let mut wm = match WM::create_wm(conn, screen_num, config, layouts, &key_handler) {
+        Ok(wm) => wm,
+        Err(ReplyOrIdError::X11Error(error)) if error.error_kind == ErrorKind::Access =>
+            fail("there is already a window manager present".to_string()),
+        Err(err) => fail(format!("failed to become the window manager: {}", Error::from(err)))
+    };
+    if let Err(err) = wm.scan(&key_handler) {
+        fail(err.to_string());
+    }
+
+    // mswm keeps working without the socket, it just cannot be scripted.
+    let path = socket_path();
+    match IpcServer::bind(&path) {
+        Ok(server) => wm.set_ipc_server(server),
+        Err(err) => warn!("failed to create the IPC socket {}: {}", path.display(), err)
+    }
+
+    let mut watcher = ConfigWatcher::new();
+    loop {
+        let wake_fds: Vec<_> = watcher.fd().into_iter().collect();
+        let mut result = wm.handle_events(&key_handler, &wake_fds);
+        if result.is_ok() && (watcher.changed() | wm.take_reload_request()) {
+            let config = Config::load(wm.layouts());
+            result = recover(wm.reload_config(config, &mut key_handler));
+        }
+        // only errors the session cannot recover from make it here.
+        if let Err(err) = result {
+            fail(err.to_string());
+        }
+    }
+}
+
+// --log-level takes precedence over MSWM_LOG, the default is info.
+fn log_level() -> LevelFilter {
+    let mut args = env::args().skip(1);
+    let mut level = env::var("MSWM_LOG").ok();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--log-level" => level = args.next(),
+            arg if arg.starts_with("--log-level=") => level = Some(arg["--log-level=".len()..].to_string()),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            },
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            }
         }
     }
+    match level.as_deref().map(str::parse) {
+        Some(Ok(level)) => level,
+        Some(Err(_)) => {
+            eprintln!("mswm: unknown log level '{}', using info", level.unwrap_or_default());
+            LevelFilter::Info
+        },
+        None => LevelFilter::Info
+    }
+}
 
-    let mut wm = wm.unwrap();
-    wm.scan(&key_handler).unwrap();
-    loop { wm.handle_events(&key_handler).unwrap(); }
+fn fail(message: String) -> ! {
+    error!("{}", message);
+    process::exit(1)
 }
@@ -0,0 +1,173 @@
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::randr::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{ConnectionExt as _, Rectangle, Window};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Monitor {
+    pub rect: Rectangle,
+    pub workspace: usize
+}
+
+impl Monitor {
+    pub fn contains(&self, x: i16, y: i16) -> bool {
+        let (x, y) = (x as i32, y as i32);
+        let r = &self.rect;
+        x >= r.x as i32 && x < r.x as i32 + r.width as i32 &&
+        y >= r.y as i32 && y < r.y as i32 + r.height as i32
+    }
+
+    pub fn center(&self) -> (i16, i16) {
+        (self.rect.x + (self.rect.width / 2) as i16, self.rect.y + (self.rect.height / 2) as i16)
+    }
+}
+
+// the space a dock reserves at the edges of the screen, see _NET_WM_STRUT_PARTIAL.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Strut {
+    left: (i32, i32, i32),
+    right: (i32, i32, i32),
+    top: (i32, i32, i32),
+    bottom: (i32, i32, i32)
+}
+
+fn overlaps(start: i32, end: i32, from: i32, len: u16) -> bool {
+    start < from + len as i32 && end >= from
+}
+
+impl Strut {
+    // takes the twelve values of _NET_WM_STRUT_PARTIAL or the four of _NET_WM_STRUT, which span the
+    // whole edge.
+    pub fn from_values(values: &[u32]) -> Option<Self> {
+        let v: Vec<i32> = values.iter().map(|v| *v as i32).collect();
+        match v.len() {
+            12 => Some(Self {
+                left: (v[0], v[4], v[5]),
+                right: (v[1], v[6], v[7]),
+                top: (v[2], v[8], v[9]),
+                bottom: (v[3], v[10], v[11])
+            }),
+            4 => Some(Self {
+                left: (v[0], 0, i32::MAX),
+                right: (v[1], 0, i32::MAX),
+                top: (v[2], 0, i32::MAX),
+                bottom: (v[3], 0, i32::MAX)
+            }),
+            _ => None
+        }
+    }
+
+    // cuts the reserved space off area, struts are relative to the edges of screen.
+    pub fn apply(&self, screen: Rectangle, area: Rectangle) -> Rectangle {
+        let (mut x1, mut y1) = (area.x as i32, area.y as i32);
+        let (mut x2, mut y2) = (x1 + area.width as i32, y1 + area.height as i32);
+        let (width, height) = (screen.width as i32, screen.height as i32);
+
+        let (size, start, end) = self.left;
+        if size > 0 && x1 < size && overlaps(start, end, area.y as i32, area.height) {
+            x1 = size;
+        }
+        let (size, start, end) = self.right;
+        if size > 0 && x2 > width - size && overlaps(start, end, area.y as i32, area.height) {
+            x2 = width - size;
+        }
+        let (size, start, end) = self.top;
+        if size > 0 && y1 < size && overlaps(start, end, area.x as i32, area.width) {
+            y1 = size;
+        }
+        let (size, start, end) = self.bottom;
+        if size > 0 && y2 > height - size && overlaps(start, end, area.x as i32, area.width) {
+            y2 = height - size;
+        }
+        Rectangle {
+            x: x1 as i16,
+            y: y1 as i16,
+            width: (x2 - x1).max(0) as u16,
+            height: (y2 - y1).max(0) as u16
+        }
+    }
+}
+
+// the area spanned by the monitors, measured from the origin of the root window.
+pub fn bounding_box(rects: impl Iterator<Item = Rectangle>) -> Rectangle {
+    let (mut x2, mut y2) = (0, 0);
+    for r in rects {
+        x2 = x2.max(r.x as i32 + r.width as i32);
+        y2 = y2.max(r.y as i32 + r.height as i32);
+    }
+    Rectangle { x: 0, y: 0, width: x2 as u16, height: y2 as u16 }
+}
+
+pub fn has_randr(conn: &impl Connection) -> Result<bool, ReplyError> {
+    Ok(conn.extension_information(randr::X11_EXTENSION_NAME)?.is_some())
+}
+
+// the areas covered by the active CRTCs from left to right, mirrored outputs are only reported
+// once. without RandR the whole root window is a single monitor.
+pub fn query_monitors(conn: &impl Connection, root: Window) -> Result<Vec<Rectangle>, ReplyError> {
+    let mut rects: Vec<Rectangle> = Vec::new();
+    if has_randr(conn)? {
+        let resources = conn.randr_get_screen_resources_current(root)?.reply()?;
+        for crtc in resources.crtcs {
+            let info = conn.randr_get_crtc_info(crtc, resources.config_timestamp)?.reply()?;
+            let rect = Rectangle { x: info.x, y: info.y, width: info.width, height: info.height };
+            if info.mode != 0 && !info.outputs.is_empty() && !rects.contains(&rect) {
+                rects.push(rect);
+            }
+        }
+    }
+    if rects.is_empty() {
+        let geom = conn.get_geometry(root)?.reply()?;
+        rects.push(Rectangle { x: 0, y: 0, width: geom.width, height: geom.height });
+    }
+    rects.sort_by_key(|r| (r.x, r.y));
+    Ok(rects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // two monitors side by side, the right one shorter: the screen is 2000x800.
+    const LEFT: Rectangle = Rectangle { x: 0, y: 0, width: 1000, height: 800 };
+    const RIGHT: Rectangle = Rectangle { x: 1000, y: 0, width: 1000, height: 600 };
+
+    fn rect(x: i16, y: i16, width: u16, height: u16) -> Rectangle {
+        Rectangle { x, y, width, height }
+    }
+
+    // the areas of both monitors after applying the _NET_WM_STRUT_PARTIAL values.
+    fn apply(values: [u32; 12]) -> (Rectangle, Rectangle) {
+        let strut = Strut::from_values(&values).unwrap();
+        let screen = bounding_box([LEFT, RIGHT].into_iter());
+        (strut.apply(screen, LEFT), strut.apply(screen, RIGHT))
+    }
+
+    #[test]
+    fn partial_struts_only_affect_the_monitors_along_their_range() {
+        // left, right, top, bottom, then the start and end of each edge in the same order.
+        assert_eq!(apply([50, 0, 0, 0, 0, 799, 0, 0, 0, 0, 0, 0]), (rect(50, 0, 950, 800), RIGHT));
+        assert_eq!(apply([0, 50, 0, 0, 0, 0, 0, 599, 0, 0, 0, 0]), (LEFT, rect(1000, 0, 950, 600)));
+        assert_eq!(apply([0, 0, 30, 0, 0, 0, 0, 0, 1000, 1999, 0, 0]), (LEFT, rect(1000, 30, 1000, 570)));
+        // the bottom of the shorter monitor lies 200 pixels above the bottom of the screen.
+        assert_eq!(apply([0, 0, 0, 230, 0, 0, 0, 0, 0, 0, 1000, 1999]), (LEFT, rect(1000, 0, 1000, 570)));
+        assert_eq!(apply([0, 0, 0, 40, 0, 0, 0, 0, 0, 0, 0, 999]), (rect(0, 0, 1000, 760), RIGHT));
+    }
+
+    #[test]
+    fn struts_outside_the_monitors_are_ignored() {
+        assert_eq!(apply([50, 0, 0, 0, 900, 1000, 0, 0, 0, 0, 0, 0]), (LEFT, RIGHT));
+        assert_eq!(apply([0, 0, 30, 0, 0, 0, 0, 0, 2500, 2600, 0, 0]), (LEFT, RIGHT));
+        // a strut within the 200 pixels below the shorter monitor does not reach it.
+        assert_eq!(apply([0, 0, 0, 100, 0, 0, 0, 0, 0, 0, 1000, 1999]), (LEFT, RIGHT));
+    }
+
+    #[test]
+    fn struts_without_a_range_span_the_whole_edge() {
+        let strut = Strut::from_values(&[0, 0, 20, 0]).unwrap();
+        let screen = bounding_box([LEFT, RIGHT].into_iter());
+        assert_eq!(strut.apply(screen, LEFT), rect(0, 20, 1000, 780));
+        assert_eq!(strut.apply(screen, RIGHT), rect(1000, 20, 1000, 580));
+        assert_eq!(Strut::from_values(&[1, 2, 3]), None);
+    }
+}
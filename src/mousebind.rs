@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Deserializer};
+use x11rb::protocol::xproto::{Button, KeyButMask, ModMask};
+
+use crate::action::{Action, Direction};
+use crate::config::{Config, MOVE_BUTTON, RESIZE_BUTTON};
+use crate::keybind::{combinations, lock_mask, parse_modifier};
+
+// what a mouse binding is clicked on. the top bar is drawn on the root window and shows the title
+// of the focused window, frames have no title bars of their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseTarget {
+    // a client or the border of its frame.
+    Window,
+    Root,
+    Bar
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MouseAction {
+    // drag the clicked window around or resize it until the button is released.
+    Move,
+    Resize,
+    Action(Action)
+}
+
+impl FromStr for MouseAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "move"   => Ok(MouseAction::Move),
+            "resize" => Ok(MouseAction::Resize),
+            _        => Ok(MouseAction::Action(s.parse()?))
+        }
+    }
+}
+
+impl fmt::Display for MouseAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MouseAction::Move           => write!(f, "move"),
+            MouseAction::Resize         => write!(f, "resize"),
+            MouseAction::Action(action) => write!(f, "{}", action)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MouseAction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+// why a mouse binding from the config cannot be used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MouseBindError {
+    NoButton(String),
+    UnknownButton(String, String),
+    UnknownModifier(String, String),
+    Duplicate(String, String)
+}
+
+impl fmt::Display for MouseBindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MouseBindError::NoButton(bind) =>
+                write!(f, "'{}' has no button, expected something like M4+left", bind),
+            MouseBindError::UnknownButton(bind, button) => write!(
+                f, "unknown button '{}' in '{}', expected left, middle, right, scroll_up, scroll_down, \
+                    scroll_left, scroll_right or button1 to button9",
+                button, bind
+            ),
+            MouseBindError::UnknownModifier(bind, modifier) =>
+                write!(f, "unknown modifier '{}' in '{}'", modifier, bind),
+            MouseBindError::Duplicate(first, second) =>
+                write!(f, "'{}' and '{}' are the same button", first, second)
+        }
+    }
+}
+
+impl std::error::Error for MouseBindError {}
+
+fn parse_button(name: &str) -> Option<Button> {
+    match name.to_ascii_lowercase().as_str() {
+        "left"         => Some(1),
+        "middle"       => Some(2),
+        "right"        => Some(3),
+        "scroll_up"    => Some(4),
+        "scroll_down"  => Some(5),
+        "scroll_left"  => Some(6),
+        "scroll_right" => Some(7),
+        name => name.strip_prefix("button")
+            .and_then(|n| n.parse().ok())
+            .filter(|n| (1..=9).contains(n))
+    }
+}
+
+// the modifiers and the button of a mouse binding, written like a keybinding: M4+scroll_up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MouseBind {
+    pub mask: u16,
+    pub button: Button
+}
+
+impl TryFrom<&str> for MouseBind {
+    type Error = MouseBindError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let value = value.trim();
+        let (modifiers, button) = value.rsplit_once('+').unwrap_or(("", value));
+        let mut mask = 0;
+        for m in modifiers.split('+').filter(|_| !modifiers.is_empty()) {
+            let modifier = parse_modifier(m)
+                .ok_or_else(|| MouseBindError::UnknownModifier(value.to_string(), m.to_string()))?;
+            mask |= u16::from(modifier);
+        }
+        if button.is_empty() || parse_modifier(button).is_some() {
+            return Err(MouseBindError::NoButton(value.to_string()));
+        }
+        let button = parse_button(button)
+            .ok_or_else(|| MouseBindError::UnknownButton(value.to_string(), button.to_string()))?;
+        Ok(MouseBind { mask, button })
+    }
+}
+
+// no two bindings of a target may use the same button.
+pub fn check_bindings<'a>(names: impl IntoIterator<Item = &'a str>) -> Result<(), MouseBindError> {
+    let mut binds = names.into_iter()
+        .map(|name| Ok((name, MouseBind::try_from(name)?)))
+        .collect::<Result<Vec<(&str, MouseBind)>, MouseBindError>>()?;
+    binds.sort_by_key(|(name, _)| *name);
+    for (i, (a, bind_a)) in binds.iter().enumerate() {
+        if let Some((b, _)) = binds[i + 1..].iter().find(|(_, bind_b)| bind_a == bind_b) {
+            return Err(MouseBindError::Duplicate(a.to_string(), b.to_string()));
+        }
+    }
+    Ok(())
+}
+
+// the mouse bindings of the config by target, without a [mousebinds] section windows are moved and
+// resized with mod_mask and scrolling on the top bar cycles the workspaces.
+pub struct MouseBindings {
+    binds: HashMap<(MouseTarget, MouseBind), MouseAction>
+}
+
+impl MouseBindings {
+    // the bindings of a loaded config have been checked already.
+    pub fn new(config: &Config) -> Self {
+        let Some(mousebinds) = &config.mousebinds else {
+            let mask = u16::from(config.mod_mask);
+            let cycle = |dir| MouseAction::Action(Action::CycleWorkspace(dir));
+            return Self { binds: HashMap::from([
+                ((MouseTarget::Window, MouseBind { mask, button: MOVE_BUTTON }), MouseAction::Move),
+                ((MouseTarget::Window, MouseBind { mask, button: RESIZE_BUTTON }), MouseAction::Resize),
+                ((MouseTarget::Bar, MouseBind { mask: 0, button: 4 }), cycle(Direction::Prev)),
+                ((MouseTarget::Bar, MouseBind { mask: 0, button: 5 }), cycle(Direction::Next))
+            ]) };
+        };
+        let mut binds = HashMap::new();
+        for (target, table) in [
+            (MouseTarget::Window, &mousebinds.window),
+            (MouseTarget::Root, &mousebinds.root),
+            (MouseTarget::Bar, &mousebinds.bar)
+        ] {
+            for (name, action) in table {
+                if let Ok(bind) = MouseBind::try_from(name.as_str()) {
+                    binds.insert((target, bind), action.clone());
+                }
+            }
+        }
+        Self { binds }
+    }
+
+    // the bindings on windows, their buttons are grabbed on every client.
+    pub fn window_binds(&self) -> impl Iterator<Item = &MouseBind> {
+        self.binds.keys().filter(|(target, _)| *target == MouseTarget::Window).map(|(_, bind)| bind)
+    }
+
+    // lock keys and the buttons already held down do not change which binding a click runs.
+    pub fn get(&self, target: MouseTarget, state: KeyButMask, button: Button) -> Option<&MouseAction> {
+        let mask = u16::from(state) & 0xff & !lock_mask();
+        self.binds.get(&(target, MouseBind { mask, button }))
+    }
+}
+
+// the modifiers of grabs on behalf of the bindings, once for every state of the lock keys.
+pub fn grab_masks(bind: MouseBind) -> impl Iterator<Item = ModMask> {
+    combinations(lock_mask() & !bind.mask).map(move |lock| ModMask::from(bind.mask | lock))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_mousebindings_are_rejected_when_loading() {
+        let load = |section: &str, binds: &str| toml::from_str::<Config>(&format!("[mousebinds.{}]\n{}", section, binds))
+            .map(|_| ()).map_err(|e| e.to_string());
+
+        assert!(load("root", r#""M4+scroll_up" = "workspace next""#).is_ok());
+        assert!(load("window", r#""middle" = "move""#).is_ok());
+        assert!(load("bar", r#""left" = "move""#).unwrap_err().contains("'left' can only move windows"));
+        assert!(load("window", r#""M4+wheel" = "stack_inc""#).unwrap_err().contains("unknown button 'wheel' in 'M4+wheel'"));
+        assert!(load("window", r#""M4+button10" = "stack_inc""#).unwrap_err().contains("unknown button"));
+        assert!(load("window", r#""M4" = "stack_inc""#).unwrap_err().contains("'M4' has no button"));
+        assert!(load("window", "\"M4+left\" = \"move\"\n\"super+button1\" = \"resize\"")
+            .unwrap_err().contains("'M4+left' and 'super+button1' are the same button"));
+        assert!(load("window", r#""M4+left" = "workspace sideways""#).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use std::str::FromStr;
+use serde::{Deserialize, Deserializer};
+
+// a shell style wildcard pattern, '*' matches any sequence and '?' any single character.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern(String);
+
+impl Pattern {
+    pub fn matches(&self, text: &str) -> bool {
+        let (pattern, text): (Vec<char>, Vec<char>) = (self.0.chars().collect(), text.chars().collect());
+        let (mut p, mut t) = (0, 0);
+        // the position of the last '*' and the text position it currently covers up to.
+        let mut backtrack: Option<(usize, usize)> = None;
+        while t < text.len() {
+            match pattern.get(p) {
+                Some('*') => {
+                    backtrack = Some((p, t));
+                    p += 1;
+                },
+                Some(c) if *c == '?' || *c == text[t] => {
+                    p += 1;
+                    t += 1;
+                },
+                _ => match backtrack {
+                    Some((star, covered)) => {
+                        p = star + 1;
+                        t = covered + 1;
+                        backtrack = Some((star, covered + 1));
+                    },
+                    None => return false
+                }
+            }
+        }
+        pattern[p..].iter().all(|c| *c == '*')
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Pattern(String::deserialize(deserializer)?))
+    }
+}
+
+// a size in pixels with an optional position relative to the monitor, written like X11
+// geometry strings: "600x400" or "600x400+20+40". without a position the window is centered.
+// unlike X11 the offsets are always counted from the top left corner and cannot be negative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    pub width: u16,
+    pub height: u16,
+    pub position: Option<(i16, i16)>
+}
+
+impl FromStr for Geometry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid geometry '{}', expected WIDTHxHEIGHT or WIDTHxHEIGHT+X+Y", s);
+        let (size, position) = match s.split_once('+') {
+            Some((size, position)) => (size, Some(position)),
+            None => (s, None)
+        };
+        let size_of = |n: &str| n.parse::<u16>().ok().filter(|n| *n > 0).ok_or_else(invalid);
+        let offset = |n: &str| n.parse::<u16>().ok().and_then(|n| i16::try_from(n).ok()).ok_or_else(invalid);
+        let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+        let (width, height) = (size_of(width)?, size_of(height)?);
+        let position = match position.map(|p| p.split_once('+')) {
+            Some(Some((x, y))) => Some((offset(x)?, offset(y)?)),
+            Some(None) => return Err(invalid()),
+            None => None
+        };
+        Ok(Self { width, height, position })
+    }
+}
+
+impl<'de> Deserialize<'de> for Geometry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+// the properties of a window rules are matched against.
+#[derive(Debug, Default)]
+pub struct WindowProperties {
+    pub class: String,
+    pub instance: String,
+    pub title: String,
+    pub role: String
+}
+
+// a rule applies to windows that match all of its patterns. workspaces and monitors are
+// numbered from 1.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rule {
+    pub class: Option<Pattern>,
+    pub instance: Option<Pattern>,
+    pub title: Option<Pattern>,
+    pub role: Option<Pattern>,
+
+    pub floating: Option<bool>,
+    #[serde(deserialize_with = "deserialize_number")]
+    pub workspace: Option<usize>,
+    #[serde(deserialize_with = "deserialize_number")]
+    pub monitor: Option<usize>,
+    pub geometry: Option<Geometry>,
+    pub border: Option<u16>,
+    pub focus: Option<bool>
+}
+
+fn deserialize_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    match usize::deserialize(deserializer)? {
+        0 => Err(serde::de::Error::custom("expected a number starting at 1")),
+        n => Ok(Some(n))
+    }
+}
+
+impl Rule {
+    pub fn matches(&self, props: &WindowProperties) -> bool {
+        [
+            (&self.class, &props.class),
+            (&self.instance, &props.instance),
+            (&self.title, &props.title),
+            (&self.role, &props.role)
+        ].iter().all(|(pattern, value)| pattern.as_ref().is_none_or(|p| p.matches(value)))
+    }
+}
+
+// what the matching rules decided for a window, later rules override earlier ones.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RuleActions {
+    pub floating: Option<bool>,
+    pub workspace: Option<usize>,
+    pub monitor: Option<usize>,
+    pub geometry: Option<Geometry>,
+    pub border: Option<u16>,
+    pub focus: Option<bool>
+}
+
+pub fn evaluate(rules: &[Rule], props: &WindowProperties) -> RuleActions {
+    rules.iter().filter(|rule| rule.matches(props)).fold(RuleActions::default(), |actions, rule| RuleActions {
+        floating: rule.floating.or(actions.floating),
+        workspace: rule.workspace.or(actions.workspace),
+        monitor: rule.monitor.or(actions.monitor),
+        geometry: rule.geometry.or(actions.geometry),
+        border: rule.border.or(actions.border),
+        focus: rule.focus.or(actions.focus)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_match_like_shell_wildcards() {
+        let matches = |pattern: &str, text: &str| Pattern(pattern.to_string()).matches(text);
+
+        assert!(matches("firefox", "firefox"));
+        assert!(!matches("firefox", "Firefox"));
+        assert!(matches("fire*", "firefox") && matches("*fox", "firefox") && matches("*", ""));
+        assert!(matches("f?ref?x", "firefox") && !matches("f?ref?x", "frefox"));
+        assert!(matches("*a*b*", "xxaxxbxx") && !matches("*a*b*", "xxbxxaxx"));
+        assert!(matches("a*b", "aaabab") && !matches("a*b", "aaaba"));
+        // patterns compare characters, not bytes.
+        assert!(matches("caf?", "café"));
+    }
+
+    #[test]
+    fn geometries_parse_with_and_without_a_position() {
+        assert_eq!("600x400".parse(), Ok(Geometry { width: 600, height: 400, position: None }));
+        assert_eq!("600x400+20+40".parse(), Ok(Geometry { width: 600, height: 400, position: Some((20, 40)) }));
+        assert_eq!("1x1+0+0".parse(), Ok(Geometry { width: 1, height: 1, position: Some((0, 0)) }));
+    }
+
+    #[test]
+    fn invalid_geometries_are_rejected() {
+        for geometry in [
+            "", "600", "600x", "x400", "0x400", "600x0", "600x400+20", "600x400+20+",
+            "600x400-10+0", "600x400+-10+0", "600x400+0+-10", "600x400+40000+0", "-600x400", "600x400x2"
+        ] {
+            assert!(geometry.parse::<Geometry>().is_err(), "{} was accepted", geometry);
+        }
+    }
+}
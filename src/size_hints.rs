@@ -0,0 +1,114 @@
+use x11rb::properties::WmSizeHints;
+
+// the constraints of WM_NORMAL_HINTS a window size has to satisfy, zero means unconstrained.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SizeHints {
+    min: (u32, u32),
+    max: (u32, u32),
+    base: (u32, u32),
+    increment: (u32, u32),
+    aspect: Option<(f64, f64)>
+}
+
+fn positive(size: Option<(i32, i32)>) -> Option<(u32, u32)> {
+    size.map(|(w, h)| (w.max(0) as u32, h.max(0) as u32))
+}
+
+impl From<&WmSizeHints> for SizeHints {
+    fn from(hints: &WmSizeHints) -> Self {
+        // ICCCM: the base size defaults to the minimum size and vice versa.
+        let min = positive(hints.min_size).or(positive(hints.base_size)).unwrap_or_default();
+        let base = positive(hints.base_size).or(positive(hints.min_size)).unwrap_or_default();
+        let aspect = hints.aspect.as_ref()
+            .filter(|(min, max)| min.denominator > 0 && max.denominator > 0)
+            .map(|(min, max)| (
+                min.numerator as f64 / min.denominator as f64,
+                max.numerator as f64 / max.denominator as f64
+            ));
+        Self {
+            min,
+            max: positive(hints.max_size).unwrap_or_default(),
+            base,
+            increment: positive(hints.size_increment).unwrap_or_default(),
+            aspect
+        }
+    }
+}
+
+impl SizeHints {
+    // the largest size that fits the hints without exceeding width x height, unless the
+    // minimum size itself is larger. X has no empty windows, so it is at least 1x1.
+    pub fn constrain(&self, width: u32, height: u32) -> (u32, u32) {
+        let (mut w, mut h) = (width.saturating_sub(self.base.0), height.saturating_sub(self.base.1));
+        if let Some((min_aspect, max_aspect)) = self.aspect {
+            if h > 0 && max_aspect > 0.0 && w as f64 / h as f64 > max_aspect {
+                w = (h as f64 * max_aspect) as u32;
+            } else if w > 0 && min_aspect > 0.0 && (w as f64 / h as f64) < min_aspect {
+                h = (w as f64 / min_aspect) as u32;
+            }
+        }
+        if self.increment.0 > 0 {
+            w -= w % self.increment.0;
+        }
+        if self.increment.1 > 0 {
+            h -= h % self.increment.1;
+        }
+        let (mut w, mut h) = ((w + self.base.0).max(self.min.0), (h + self.base.1).max(self.min.1));
+        if self.max.0 > 0 {
+            w = w.min(self.max.0);
+        }
+        if self.max.1 > 0 {
+            h = h.min(self.max.1);
+        }
+        (w.max(1), h.max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x11rb::properties::AspectRatio;
+
+    fn hints(setup: impl FnOnce(&mut WmSizeHints)) -> SizeHints {
+        let mut hints = WmSizeHints::new();
+        setup(&mut hints);
+        SizeHints::from(&hints)
+    }
+
+    #[test]
+    fn sizes_are_kept_between_min_and_max() {
+        let hints = hints(|h| {
+            h.min_size = Some((100, 50));
+            h.max_size = Some((400, 300));
+        });
+        assert_eq!(hints.constrain(50, 20), (100, 50));
+        assert_eq!(hints.constrain(200, 200), (200, 200));
+        assert_eq!(hints.constrain(1000, 1000), (400, 300));
+    }
+
+    #[test]
+    fn increments_are_counted_from_the_base_size() {
+        let hints = hints(|h| {
+            h.base_size = Some((10, 5));
+            h.size_increment = Some((8, 16));
+        });
+        assert_eq!(hints.constrain(100, 100), (98, 85));
+        // the base size doubles as the minimum size.
+        assert_eq!(hints.constrain(4, 4), (10, 5));
+    }
+
+    #[test]
+    fn the_aspect_ratio_shrinks_the_longer_side() {
+        let hints = hints(|h| h.aspect = Some((AspectRatio::new(1, 1), AspectRatio::new(2, 1))));
+        assert_eq!(hints.constrain(500, 100), (200, 100));
+        assert_eq!(hints.constrain(100, 500), (100, 100));
+        assert_eq!(hints.constrain(150, 100), (150, 100));
+    }
+
+    #[test]
+    fn sizes_never_drop_to_zero() {
+        assert_eq!(SizeHints::default().constrain(0, 0), (1, 1));
+        let hints = hints(|h| h.size_increment = Some((50, 50)));
+        assert_eq!(hints.constrain(30, 30), (1, 1));
+    }
+}
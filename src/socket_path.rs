@@ -0,0 +1,18 @@
+use std::env;
+use std::path::PathBuf;
+use nix::unistd::getuid;
+
+// MSWM_SOCKET overrides the default of one socket per display in $XDG_RUNTIME_DIR. without it the
+// socket goes into a directory of the user under /tmp, which the IPC server creates private.
+pub fn socket_path() -> PathBuf {
+    if let Some(path) = env::var_os("MSWM_SOCKET") {
+        return PathBuf::from(path);
+    }
+    let dir = env::var_os("XDG_RUNTIME_DIR")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| env::temp_dir().join(format!("mswm-{}", getuid())));
+    let display = env::var("DISPLAY").unwrap_or_default();
+    let display: String = display.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '.').collect();
+    dir.join(format!("mswm-{}.sock", display))
+}
@@ -1,38 +1,97 @@
 use std::cmp;
 use std::cmp::Reverse;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::{io, iter, mem};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::{Duration, Instant};
+use log::{debug, info, warn};
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use serde_json::{json, Value};
 use x11rb::connection::Connection;
-use x11rb::errors::{ReplyError, ReplyOrIdError};
+use x11rb::errors::{ConnectionError, ReplyError, ReplyOrIdError};
 use x11rb::protocol::xproto::*;
+use x11rb::properties::{WmClass, WmHints, WmSizeHints};
+use x11rb::protocol::randr::{ConnectionExt as _, NotifyMask};
+use x11rb::protocol::xkb::{self, ConnectionExt as _};
 use x11rb::protocol::Event;
 use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
 use x11rb::COPY_DEPTH_FROM_PARENT;
 
+use crate::atoms::Atoms;
 use crate::config::*;
-use crate::keybind::KeyHandler;
-use crate::layout::{FibonacciLayout, TreeLayout, WindowLayout};
+use crate::error::{recover, Error};
+use crate::ipc::{EventKind, IpcServer, Request};
+use crate::keybind::{KeyBindHandler, KeyHandler};
+use crate::layout::{LayoutParams, LayoutRegistry, WindowLayout};
+use crate::mousebind::{grab_masks, MouseAction, MouseBindings, MouseTarget};
+use crate::monitor::{bounding_box, has_randr, query_monitors, Monitor, Strut};
+use crate::rules::{self, WindowProperties};
+use crate::size_hints::SizeHints;
+use crate::workspace::Workspace;
 
-pub struct WM {
-    conn: RustConnection,
+// the X connection the WM runs on, which is a fake one in tests.
+pub trait XConnection: Connection {
+    // the file descriptor that becomes readable when events arrive.
+    fn raw_fd(&self) -> RawFd;
+}
+
+impl XConnection for RustConnection {
+    fn raw_fd(&self) -> RawFd {
+        self.stream().as_raw_fd()
+    }
+}
+
+pub struct WM<C: XConnection = RustConnection> {
+    conn: C,
     screen_num: usize,
 
     move_flag: bool,
+    // the button that has to be released to end the drag of the window.
+    drag_button: Button,
     window: Option<(Window, i16, i16, i32, i32, i32, i32)>,
     focused: Option<Window>,
 
     gc: Gcontext,
+    atoms: Atoms,
     sequence_ignore: BinaryHeap<Reverse<u16>>,
+    // the time of the latest event that carries one, ICCCM asks focus changes not to use CurrentTime.
+    event_time: Timestamp,
     window_map: HashMap<Window, Window>,
     window_map_reverse: HashMap<Window, Window>,
+    client_list: Vec<Window>,
+    pending_kills: HashMap<Window, Instant>,
+    size_hints: HashMap<Window, SizeHints>,
+    // clients that stay floating when the layout is applied, and the parents of transient ones.
+    always_floating: HashSet<Window>,
+    transient_for: HashMap<Window, Window>,
+    // unframed windows like external bars that only reserve space at the screen edges.
+    docks: HashMap<Window, Strut>,
+    // fullscreen clients and the geometry of their frame before.
+    fullscreen: HashMap<Window, Rectangle>,
+    // frame borders set by window rules, every other frame has a border of 1.
+    borders: HashMap<Window, u16>,
 
-    tiling_win_stack: Vec<Window>,
-    floating_win_stack: Vec<Window>,
+    workspaces: Vec<Workspace>,
+    monitors: Vec<Monitor>,
+    current_monitor: usize,
 
-    pub layout_flag: bool
+    layouts: LayoutRegistry,
+    ipc: Option<IpcServer>,
+    ipc_events: Vec<(EventKind, Value)>,
+    config: Config,
+    reload_requested: bool,
+    mousebinds: MouseBindings,
+    // the active keybinding mode other than the default one and the keys of a half typed chord,
+    // the keyboard is grabbed while either is set.
+    key_mode: Option<String>,
+    key_chord: Option<String>
 }
 
-impl WM {
-    pub fn create_wm(conn: RustConnection, screen_num: usize, key_handler: &impl KeyHandler) -> Result<Self, ReplyOrIdError> {
+impl<C: XConnection> WM<C> {
+    pub fn create_wm(conn: C, screen_num: usize, mut config: Config, layouts: LayoutRegistry, key_handler: &impl KeyHandler<C>) -> Result<Self, ReplyOrIdError> {
+        check_default_layout(&mut config, &layouts);
         let screen = &conn.setup().roots[screen_num];
         key_handler.grab_keys(&conn, screen.root)?;
         let change = ChangeWindowAttributesAux::default()
@@ -54,41 +113,517 @@ impl WM {
             .font(font))?;
         conn.close_font(font)?;
 
-        Ok(Self {
+        if has_randr(&conn)? {
+            conn.randr_query_version(1, 3)?.reply()?;
+            conn.randr_select_input(screen.root, NotifyMask::SCREEN_CHANGE)?;
+        }
+        // core MappingNotify events are not sent when a different keyboard is plugged in.
+        if conn.extension_information(xkb::X11_EXTENSION_NAME)?.is_some()
+            && conn.xkb_use_extension(1, 0)?.reply()?.supported {
+            conn.xkb_select_events(
+                xkb::ID::USE_CORE_KBD.into(),
+                xkb::EventType::from(0u16),
+                xkb::EventType::NEW_KEYBOARD_NOTIFY,
+                xkb::MapPart::from(0u16),
+                xkb::MapPart::from(0u16),
+                &xkb::SelectEventsAux::new()
+            )?;
+        }
+        // every monitor needs a workspace of its own to show.
+        let rects = query_monitors(&conn, screen.root)?;
+        let workspace_count = cmp::max(config.workspaces, rects.len());
+        let monitors = rects.into_iter().enumerate()
+            .map(|(workspace, rect)| Monitor { rect, workspace })
+            .collect();
+
+        let atoms = Atoms::new(&conn)?.reply()?;
+        Self::init_ewmh(&conn, screen.root, &atoms, workspace_count)?;
+
+        let wm = Self {
             conn,
             screen_num,
             move_flag: false,
+            drag_button: MOVE_BUTTON,
             window: None,
             focused: None,
             gc,
+            atoms,
             sequence_ignore: BinaryHeap::new(),
+            event_time: x11rb::CURRENT_TIME,
             window_map: HashMap::new(),
             window_map_reverse: HashMap::new(),
-            tiling_win_stack: Vec::new(),
-            floating_win_stack: Vec::new(),
-            layout_flag: false
-        })
+            client_list: Vec::new(),
+            pending_kills: HashMap::new(),
+            size_hints: HashMap::new(),
+            always_floating: HashSet::new(),
+            transient_for: HashMap::new(),
+            docks: HashMap::new(),
+            fullscreen: HashMap::new(),
+            borders: HashMap::new(),
+            workspaces: (0..workspace_count)
+                .map(|_| Workspace::new(&config.layout))
+                .collect(),
+            monitors,
+            current_monitor: 0,
+            layouts,
+            ipc: None,
+            ipc_events: Vec::new(),
+            mousebinds: MouseBindings::new(&config),
+            config,
+            reload_requested: false,
+            key_mode: None,
+            key_chord: None
+        };
+        wm.update_workarea()?;
+        Ok(wm)
+    }
+
+    // advertises the supported hints and creates the child window required by _NET_SUPPORTING_WM_CHECK.
+    fn init_ewmh(conn: &C, root: Window, atoms: &Atoms, workspaces: usize) -> Result<(), ReplyOrIdError> {
+        let check_win = conn.generate_id()?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            check_win,
+            root,
+            -1, -1, 1, 1, 0,
+            WindowClass::INPUT_OUTPUT,
+            0,
+            &CreateWindowAux::new(),
+        )?;
+        for win in [root, check_win] {
+            conn.change_property32(PropMode::REPLACE, win, atoms._NET_SUPPORTING_WM_CHECK, AtomEnum::WINDOW, &[check_win])?;
+        }
+        conn.change_property8(PropMode::REPLACE, check_win, atoms._NET_WM_NAME, atoms.UTF8_STRING, b"mswm")?;
+        conn.change_property32(PropMode::REPLACE, root, atoms._NET_SUPPORTED, AtomEnum::ATOM, &atoms.supported())?;
+        conn.change_property32(PropMode::REPLACE, root, atoms._NET_CLIENT_LIST, AtomEnum::WINDOW, &[])?;
+        conn.change_property32(PropMode::REPLACE, root, atoms._NET_CLIENT_LIST_STACKING, AtomEnum::WINDOW, &[])?;
+        conn.change_property32(PropMode::REPLACE, root, atoms._NET_ACTIVE_WINDOW, AtomEnum::WINDOW, &[x11rb::NONE])?;
+        conn.change_property32(PropMode::REPLACE, root, atoms._NET_NUMBER_OF_DESKTOPS, AtomEnum::CARDINAL, &[workspaces as u32])?;
+        conn.change_property32(PropMode::REPLACE, root, atoms._NET_CURRENT_DESKTOP, AtomEnum::CARDINAL, &[0])?;
+        conn.flush()?;
+        Ok(())
+    }
+
+    fn update_client_list(&self) -> Result<(), ReplyError> {
+        let root = self.conn.setup().roots[self.screen_num].root;
+        self.conn.change_property32(PropMode::REPLACE, root, self.atoms._NET_CLIENT_LIST, AtomEnum::WINDOW, &self.client_list)?;
+        // the children of root are returned in stacking order, bottom to top.
+        let stacking: Vec<Window> = self.conn.query_tree(root)?.reply()?.children.iter()
+            .filter_map(|frame| self.window_map_reverse.get(frame).copied())
+            .collect();
+        self.conn.change_property32(PropMode::REPLACE, root, self.atoms._NET_CLIENT_LIST_STACKING, AtomEnum::WINDOW, &stacking)?;
+        Ok(())
+    }
+
+    fn update_active_window(&self) -> Result<(), ReplyError> {
+        let root = self.conn.setup().roots[self.screen_num].root;
+        let active = self.focused.unwrap_or(x11rb::NONE);
+        self.conn.change_property32(PropMode::REPLACE, root, self.atoms._NET_ACTIVE_WINDOW, AtomEnum::WINDOW, &[active])?;
+        Ok(())
+    }
+
+    fn update_window_desktop(&self, win: Window, workspace: usize) -> Result<(), ReplyError> {
+        self.conn.change_property32(PropMode::REPLACE, win, self.atoms._NET_WM_DESKTOP, AtomEnum::CARDINAL, &[workspace as u32])?;
+        Ok(())
+    }
+
+    fn update_current_desktop(&mut self) -> Result<(), ReplyError> {
+        let root = self.conn.setup().roots[self.screen_num].root;
+        let workspace = self.current_workspace();
+        self.conn.change_property32(PropMode::REPLACE, root, self.atoms._NET_CURRENT_DESKTOP, AtomEnum::CARDINAL, &[workspace as u32])?;
+        self.emit(EventKind::Workspace, json!({ "workspace": workspace + 1, "monitor": self.current_monitor + 1 }));
+        Ok(())
+    }
+
+    // the monitor without the space reserved by docks.
+    fn usable_area(&self, monitor: usize) -> Rectangle {
+        let screen = bounding_box(self.monitors.iter().map(|m| m.rect));
+        self.docks.values().fold(self.monitors[monitor].rect, |area, strut| strut.apply(screen, area))
+    }
+
+    // the space left for windows below the top bar.
+    fn layout_area(&self, monitor: usize) -> Rectangle {
+        let area = self.usable_area(monitor);
+        let bar_height = self.config.top_bar.height;
+        Rectangle {
+            x: area.x,
+            y: area.y + bar_height as i16,
+            width: area.width,
+            height: area.height.saturating_sub(bar_height)
+        }
+    }
+
+    // EWMH only knows a single work area per desktop, so it spans all monitors.
+    fn update_workarea(&self) -> Result<(), ReplyError> {
+        let root = self.conn.setup().roots[self.screen_num].root;
+        let screen = bounding_box(self.monitors.iter().map(|m| m.rect));
+        let area = self.docks.values().fold(screen, |area, strut| strut.apply(screen, area));
+        let bar_height = self.config.top_bar.height;
+        let values: Vec<u32> = (0..self.workspaces.len())
+            .flat_map(|_| [
+                area.x as u32,
+                area.y as u32 + bar_height as u32,
+                area.width as u32,
+                area.height.saturating_sub(bar_height) as u32
+            ])
+            .collect();
+        self.conn.change_property32(PropMode::REPLACE, root, self.atoms._NET_WORKAREA, AtomEnum::CARDINAL, &values)?;
+        Ok(())
+    }
+
+    fn retile_all(&mut self) -> Result<(), ReplyError> {
+        for monitor in 0..self.monitors.len() {
+            self.create_new_layout(monitor)?;
+        }
+        Ok(())
+    }
+
+    fn current_workspace(&self) -> usize {
+        self.monitors[self.current_monitor].workspace
+    }
+
+    pub fn workspace(&self) -> &Workspace {
+        &self.workspaces[self.current_workspace()]
+    }
+
+    pub fn workspace_mut(&mut self) -> &mut Workspace {
+        let workspace = self.current_workspace();
+        &mut self.workspaces[workspace]
+    }
+
+    fn workspace_of(&self, frame: Window) -> Option<usize> {
+        self.workspaces.iter().position(|ws| ws.contains(frame))
+    }
+
+    fn monitor_of(&self, workspace: usize) -> Option<usize> {
+        self.monitors.iter().position(|m| m.workspace == workspace)
+    }
+
+    fn set_current_monitor(&mut self, monitor: usize) -> Result<(), ReplyError> {
+        if monitor != self.current_monitor {
+            self.current_monitor = monitor;
+            self.update_current_desktop()?;
+            self.draw_top_bar()?;
+        }
+        Ok(())
+    }
+
+    // unmapping a frame leaves its client mapped, yet the resulting events are ignored so
+    // they are not mistaken for a client withdrawing its window.
+    fn hide_frame(&mut self, frame: Window) -> Result<(), ReplyError> {
+        let cookie = self.conn.unmap_window(frame)?;
+        self.sequence_ignore.push(
+            Reverse(cookie.sequence_number() as u16));
+        Ok(())
+    }
+
+    // a workspace that is already visible on another monitor is reached by focusing that monitor.
+    pub fn switch_workspace(&mut self, workspace: usize) -> Result<(), ReplyOrIdError> {
+        if workspace >= self.workspaces.len() {
+            return Ok(());
+        }
+        if let Some(monitor) = self.monitor_of(workspace) {
+            return self.select_monitor(monitor);
+        }
+        let hidden: Vec<Window> = self.workspace().frames().copied().collect();
+        for frame in hidden {
+            self.hide_frame(frame)?;
+        }
+        self.monitors[self.current_monitor].workspace = workspace;
+        for frame in self.workspace().frames() {
+            self.conn.map_window(*frame)?;
+        }
+
+        self.update_current_desktop()?;
+        self.focused = None;
+        self.focus_window(self.workspace().focus_history.last().copied())?;
+        self.create_new_layout(self.current_monitor)?;
+        Ok(())
+    }
+
+    // the workspace before or after the current one, wrapping around at the ends.
+    pub fn cycle_workspace(&mut self, delta: i32) -> Result<(), ReplyOrIdError> {
+        let workspace = (self.current_workspace() as i32 + delta).rem_euclid(self.workspaces.len() as i32);
+        self.switch_workspace(workspace as usize)
+    }
+
+    pub fn move_focused_to_workspace(&mut self, workspace: usize) -> Result<(), ReplyOrIdError> {
+        if let Some(win) = self.focused {
+            self.move_to_workspace(win, workspace)?;
+        }
+        Ok(())
+    }
+
+    fn move_to_workspace(&mut self, win: Window, workspace: usize) -> Result<(), ReplyOrIdError> {
+        let frame = match self.window_map.get(&win) {
+            Some(frame) => *frame,
+            None => return Ok(())
+        };
+        let source = match self.workspace_of(frame) {
+            Some(source) if source != workspace && workspace < self.workspaces.len() => source,
+            _ => return Ok(())
+        };
+        let tiled = self.workspaces[source].remove(frame).unwrap_or(false);
+        self.workspaces[source].focus_history.retain(|w| *w != win);
+        let target = &mut self.workspaces[workspace];
+        if tiled {
+            target.tiling_win_stack.push(frame);
+        } else {
+            target.floating_win_stack.push(frame);
+        }
+        self.update_window_desktop(win, workspace)?;
+
+        let (source_monitor, target_monitor) = (self.monitor_of(source), self.monitor_of(workspace));
+        match (source_monitor, target_monitor) {
+            (Some(_), None) => self.hide_frame(frame)?,
+            (None, Some(_)) => { self.conn.map_window(frame)?; },
+            (Some(from), Some(to)) if !tiled => {
+                self.translate_frame(frame, self.monitors[from].rect, self.monitors[to].rect)?;
+            },
+            _ => {}
+        }
+        if self.focused == Some(win) && target_monitor.is_none() {
+            self.focused = None;
+            self.focus_window(self.workspace().focus_history.last().copied())?;
+        }
+        if tiled || self.fullscreen.contains_key(&win) {
+            for monitor in [source_monitor, target_monitor].into_iter().flatten() {
+                self.create_new_layout(monitor)?;
+            }
+        }
+        // dialogs follow their parent.
+        for child in self.transients_of(win) {
+            self.move_to_workspace(child, workspace)?;
+        }
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn transients_of(&self, win: Window) -> Vec<Window> {
+        self.transient_for.iter()
+            .filter(|(_, parent)| **parent == win)
+            .map(|(child, _)| *child)
+            .collect()
+    }
+
+    // raises the frame of the client together with its transients, which stay on top.
+    fn raise(&self, win: Window) -> Result<(), ReplyError> {
+        if let Some(frame) = self.window_map.get(&win) {
+            self.conn.configure_window(*frame, &ConfigureWindowAux::new()
+                .stack_mode(StackMode::ABOVE))?;
+        }
+        for child in self.transients_of(win) {
+            self.raise(child)?;
+        }
+        Ok(())
+    }
+
+    // a parent that is itself transient for the window is ignored, the windows would raise each other forever.
+    fn read_transient_for(&self, win: Window) -> Result<Option<Window>, ReplyError> {
+        let reply = self.conn.get_property(false, win, AtomEnum::WM_TRANSIENT_FOR, AtomEnum::WINDOW, 0, 1)?.reply()?;
+        Ok(reply.value32().and_then(|mut v| v.next())
+            .filter(|parent| *parent != x11rb::NONE)
+            .filter(|parent| !iter::successors(Some(*parent), |w| self.transient_for.get(w).copied()).any(|w| w == win)))
+    }
+
+    fn window_types(&self, win: Window) -> Result<Vec<Atom>, ReplyError> {
+        let reply = self.conn.get_property(false, win, self.atoms._NET_WM_WINDOW_TYPE, AtomEnum::ATOM, 0, 32)?.reply()?;
+        Ok(reply.value32().into_iter().flatten().collect())
+    }
+
+    fn window_states(&self, win: Window) -> Result<Vec<Atom>, ReplyError> {
+        let reply = self.conn.get_property(false, win, self.atoms._NET_WM_STATE, AtomEnum::ATOM, 0, 32)?.reply()?;
+        Ok(reply.value32().into_iter().flatten().collect())
+    }
+
+    // focuses the last active window of the monitor and moves the pointer along.
+    fn select_monitor(&mut self, monitor: usize) -> Result<(), ReplyOrIdError> {
+        if monitor == self.current_monitor {
+            return Ok(());
+        }
+        self.set_current_monitor(monitor)?;
+        self.focused = None;
+        self.focus_window(self.workspace().focus_history.last().copied())?;
+        let root = self.conn.setup().roots[self.screen_num].root;
+        let (x, y) = self.monitors[monitor].center();
+        self.conn.warp_pointer(x11rb::NONE, root, 0, 0, 0, 0, x, y)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn relative_monitor(&self, delta: i32) -> usize {
+        (self.current_monitor as i32 + delta).rem_euclid(self.monitors.len() as i32) as usize
+    }
+
+    pub fn focus_monitor(&mut self, delta: i32) -> Result<(), ReplyOrIdError> {
+        self.select_monitor(self.relative_monitor(delta))
     }
 
-    pub fn scan(&mut self, key_handler: &impl KeyHandler) -> Result<(), ReplyOrIdError> {
+    pub fn move_focused_to_monitor(&mut self, delta: i32) -> Result<(), ReplyOrIdError> {
+        let workspace = self.monitors[self.relative_monitor(delta)].workspace;
+        self.move_focused_to_workspace(workspace)
+    }
+
+    // floating windows keep their position relative to the monitor, as far as they fit on the new one.
+    fn translate_frame(&self, frame: Window, from: Rectangle, to: Rectangle) -> Result<(), ReplyError> {
+        let geom = self.conn.get_geometry(frame)?.reply()?;
+        let place = |pos: i16, size: u16, from: i16, to: i16, to_size: u16| {
+            let max = to as i32 + cmp::max(to_size as i32 - size as i32, 0);
+            (pos as i32 - from as i32 + to as i32).clamp(to as i32, max)
+        };
+        self.conn.configure_window(frame, &ConfigureWindowAux::new()
+            .x(place(geom.x, geom.width, from.x, to.x, to.width))
+            .y(place(geom.y, geom.height, from.y, to.y, to.height)))?;
+        Ok(())
+    }
+
+    // re-reads the monitor layout after outputs were added, removed or reconfigured.
+    fn update_monitors(&mut self) -> Result<(), ReplyOrIdError> {
+        let root = self.conn.setup().roots[self.screen_num].root;
+        let mut rects = query_monitors(&self.conn, root)?;
+        let current_workspace = self.current_workspace();
+
+        // monitors whose area did not change keep their workspace, the others take the remaining
+        // areas from left to right and the rest went away.
+        let (mut monitors, changed): (Vec<Monitor>, Vec<Monitor>) = mem::take(&mut self.monitors).into_iter()
+            .partition(|m| rects.contains(&m.rect));
+        rects.retain(|r| !monitors.iter().any(|m| m.rect == *r));
+        let mut removed = Vec::new();
+        for mut monitor in changed {
+            if rects.is_empty() {
+                removed.push(monitor);
+            } else {
+                monitor.rect = rects.remove(0);
+                monitors.push(monitor);
+            }
+        }
+        monitors.sort_by_key(|m| (m.rect.x, m.rect.y));
+        self.monitors = monitors;
+        for rect in rects {
+            // new monitors show the first hidden workspace, if there is none another one is added.
+            let workspace = match (0..self.workspaces.len()).find(|ws| self.monitor_of(*ws).is_none()) {
+                Some(workspace) => workspace,
+                None => {
+                    self.workspaces.push(Workspace::new(&self.config.layout));
+                    self.conn.change_property32(PropMode::REPLACE, root, self.atoms._NET_NUMBER_OF_DESKTOPS, AtomEnum::CARDINAL, &[self.workspaces.len() as u32])?;
+                    self.workspaces.len() - 1
+                }
+            };
+            self.monitors.push(Monitor { rect, workspace });
+            for frame in self.workspaces[workspace].frames() {
+                self.conn.map_window(*frame)?;
+            }
+        }
+        self.monitors.sort_by_key(|m| (m.rect.x, m.rect.y));
+        self.current_monitor = self.monitor_of(current_workspace).unwrap_or(0);
+
+        // the windows of monitors that went away move to the first monitor.
+        let target = self.monitors[0];
+        for monitor in removed {
+            let frames: Vec<Window> = self.workspaces[monitor.workspace].frames().copied().collect();
+            for frame in frames {
+                let Some(win) = self.window_map_reverse.get(&frame).copied() else { continue };
+                let floating = !self.workspaces[monitor.workspace].tiling_win_stack.contains(&frame);
+                self.move_to_workspace(win, target.workspace)?;
+                if floating && !self.fullscreen.contains_key(&win) {
+                    self.translate_frame(frame, monitor.rect, target.rect)?;
+                }
+            }
+        }
+
+        self.update_current_desktop()?;
+        self.update_workarea()?;
+        self.retile_all()?;
+        Ok(())
+    }
+
+    pub fn scan(&mut self, key_handler: &impl KeyHandler<C>) -> Result<(), Error> {
         let screen = &self.conn.setup().roots[self.screen_num];
         let children = self.conn.query_tree(screen.root)?.reply()?.children;
         for win in children {
-            let attr = self.conn.get_window_attributes(win)?.reply()?;
-            if attr.map_state != MapState::UNMAPPED && !attr.override_redirect {
-                self.manage(win, key_handler)?;
-            }
+            // windows may vanish while they are being looked at.
+            recover(self.scan_window(win, key_handler))?;
         }
         Ok(())
     }
 
-    fn manage(&mut self, win: Window, key_handler: &impl KeyHandler) -> Result<(), ReplyOrIdError> {
+    fn scan_window(&mut self, win: Window, key_handler: &impl KeyHandler<C>) -> Result<(), ReplyOrIdError> {
+        let attr = self.conn.get_window_attributes(win)?.reply()?;
+        if attr.map_state != MapState::UNMAPPED && !attr.override_redirect {
+            self.manage(win, key_handler)?;
+        }
+        Ok(())
+    }
+
+    fn manage(&mut self, win: Window, key_handler: &impl KeyHandler<C>) -> Result<(), ReplyOrIdError> {
         let screen = &self.conn.setup().roots[self.screen_num];
+        let types = self.window_types(win)?;
+        if types.contains(&self.atoms._NET_WM_WINDOW_TYPE_DOCK) {
+            return Ok(self.manage_dock(win)?);
+        }
         let geom = self.conn.get_geometry(win)?.reply()?;
+        let hints = self.read_size_hints(win)?;
+        self.size_hints.insert(win, hints);
+        let parent = self.read_transient_for(win)?;
+        let props = self.window_properties(win)?;
+        let rules = rules::evaluate(&self.config.rules, &props);
+
+        // transients open on the workspace of their parent unless a rule says otherwise.
+        let parent_frame = parent.and_then(|p| self.window_map.get(&p).copied());
+        let workspace = rules.workspace.map(|n| n - 1).filter(|ws| *ws < self.workspaces.len())
+            .or_else(|| rules.monitor.and_then(|n| self.monitors.get(n - 1)).map(|m| m.workspace))
+            .or_else(|| parent_frame.and_then(|frame| self.workspace_of(frame)))
+            .unwrap_or_else(|| self.current_workspace());
+        let floating = rules.floating.unwrap_or_else(||
+            parent.is_some() || self.atoms.floating_types().iter().any(|t| types.contains(t)));
+
+        let (width, height) = match rules.geometry {
+            Some(geometry) => hints.constrain(geometry.width as u32, geometry.height as u32),
+            None => hints.constrain(geom.width as u32, geom.height as u32)
+        };
+        let area = self.layout_area(self.monitor_of(workspace).unwrap_or(self.current_monitor));
+        let (x, y) = match rules.geometry.and_then(|g| g.position) {
+            Some((x, y)) => (area.x.saturating_add(x), area.y.saturating_add(y)),
+            // dialogs are centered above their parent, other floating windows on their monitor.
+            None if floating || rules.geometry.is_some() => {
+                let area = match parent_frame.filter(|_| rules.workspace.is_none() && rules.monitor.is_none()) {
+                    Some(frame) => {
+                        let geom = self.conn.get_geometry(frame)?.reply()?;
+                        Rectangle { x: geom.x, y: geom.y, width: geom.width, height: geom.height }
+                    },
+                    None => area
+                };
+                // windows larger than the area stick out on both sides, as far as coordinates reach.
+                let center = |start: i16, outer: u16, inner: u32|
+                    (start as i64 + (outer as i64 - inner as i64) / 2).clamp(i16::MIN as i64, i16::MAX as i64) as i16;
+                (center(area.x, area.width, width), center(area.y, area.height, height))
+            },
+            None => (geom.x, geom.y + (self.config.top_bar.height as i16))
+        };
+        let border = rules.border.unwrap_or(1);
+        info!("managing {:#x} ({}/{}) on workspace {}{}", win, props.class, props.instance, workspace + 1,
+            if floating { ", floating" } else { "" });
+
         let frame_win = self.conn.generate_id()?;
         self.window_map.insert(win, frame_win);
         self.window_map_reverse.insert(frame_win, win);
-        self.floating_win_stack.push(frame_win);
+        self.client_list.push(win);
+        if floating {
+            self.always_floating.insert(win);
+        }
+        if let Some(parent) = parent {
+            self.transient_for.insert(win, parent);
+        }
+        if rules.border.is_some() {
+            self.borders.insert(win, border);
+        }
+        // a rule that disables floating tiles the window right away.
+        if rules.floating == Some(false) {
+            self.workspaces[workspace].tiling_win_stack.push(frame_win);
+        } else {
+            self.workspaces[workspace].floating_win_stack.push(frame_win);
+        }
+        self.update_window_desktop(win, workspace)?;
 
         let win_aux = CreateWindowAux::new()
             .event_mask(EventMask::ENTER_WINDOW |
@@ -100,53 +635,178 @@ impl WM {
             COPY_DEPTH_FROM_PARENT,
             frame_win,
             screen.root,
-            geom.x,
-            geom.y + (TOP_BAR_HEIGHT as i16),
-            geom.width,
-            geom.height,
-            1,
+            x,
+            y,
+            width as u16,
+            height as u16,
+            border,
             WindowClass::INPUT_OUTPUT,
             0,
             &win_aux,
         )?;
 
         self.conn.grab_server()?;
-        let cookie = self.conn.reparent_window(win, frame_win, 0, 0)?;
-        self.sequence_ignore.push(
-            Reverse(cookie.sequence_number() as u16));
+        self.conn.change_window_attributes(win, &ChangeWindowAttributesAux::new()
+            .event_mask(EventMask::PROPERTY_CHANGE))?;
+        self.conn.configure_window(win, &ConfigureWindowAux::new().width(width).height(height))?;
+        let sequence = self.conn.reparent_window(win, frame_win, 0, 0)?.sequence_number();
+        self.sequence_ignore.push(Reverse(sequence as u16));
         self.conn.map_window(win)?;
-        self.conn.map_window(frame_win)?;
+        if self.monitor_of(workspace).is_some() {
+            self.conn.map_window(frame_win)?;
+        }
         self.grab_buttons(win)?;
         self.grab_keys(key_handler, win)?;
         self.conn.ungrab_server()?;
+        self.update_client_list()?;
+        // clients like video players ask for fullscreen before they are mapped.
+        if self.window_states(win)?.contains(&self.atoms._NET_WM_STATE_FULLSCREEN) {
+            self.set_fullscreen(win, true)?;
+        }
+        let monitor = self.monitor_of(workspace);
+        if let (Some(monitor), Some(false)) = (monitor, rules.floating) {
+            self.create_new_layout(monitor)?;
+        }
+        if monitor.is_some() && rules.focus == Some(true) {
+            self.raise(win)?;
+            self.focus_window(Some(win))?;
+        }
+        self.emit(EventKind::Window, json!({ "change": "manage", "window": win, "workspace": workspace + 1 }));
         self.conn.flush()?;
         Ok(())
     }
 
+    fn window_properties(&self, win: Window) -> Result<WindowProperties, ReplyError> {
+        let (instance, class) = match WmClass::get(&self.conn, win)?.reply() {
+            Ok(wm_class) => (
+                String::from_utf8_lossy(wm_class.instance()).into_owned(),
+                String::from_utf8_lossy(wm_class.class()).into_owned()
+            ),
+            Err(ReplyError::ConnectionError(ConnectionError::ParseError(_))) => Default::default(),
+            Err(err) => return Err(err)
+        };
+        let mut title = self.read_text_property(win, self.atoms._NET_WM_NAME)?;
+        if title.is_empty() {
+            title = self.read_text_property(win, AtomEnum::WM_NAME.into())?;
+        }
+        let role = self.read_text_property(win, self.atoms.WM_WINDOW_ROLE)?;
+        Ok(WindowProperties { class, instance, title, role })
+    }
+
+    fn read_text_property(&self, win: Window, property: Atom) -> Result<String, ReplyError> {
+        let reply = self.conn.get_property(false, win, property, AtomEnum::ANY, 0, u32::MAX)?.reply()?;
+        Ok(String::from_utf8_lossy(&reply.value).into_owned())
+    }
+
+    fn manage_dock(&mut self, win: Window) -> Result<(), ReplyError> {
+        info!("managing dock {:#x}", win);
+        self.conn.change_window_attributes(win, &ChangeWindowAttributesAux::new()
+            .event_mask(EventMask::PROPERTY_CHANGE))?;
+        let strut = self.read_strut(win)?;
+        self.docks.insert(win, strut);
+        self.conn.map_window(win)?;
+        self.update_workarea()?;
+        self.retile_all()
+    }
+
+    fn read_strut(&self, win: Window) -> Result<Strut, ReplyError> {
+        for (atom, len) in [(self.atoms._NET_WM_STRUT_PARTIAL, 12), (self.atoms._NET_WM_STRUT, 4)] {
+            let reply = self.conn.get_property(false, win, atom, AtomEnum::CARDINAL, 0, len)?.reply()?;
+            let values: Vec<u32> = reply.value32().into_iter().flatten().collect();
+            if let Some(strut) = Strut::from_values(&values) {
+                return Ok(strut);
+            }
+        }
+        Ok(Strut::default())
+    }
+
     fn unmanage(&mut self, win: Window) -> Result<(), ReplyError> {
+        if self.docks.remove(&win).is_some() {
+            info!("unmanaging dock {:#x}", win);
+            self.update_workarea()?;
+            return self.retile_all();
+        }
         if let Some(parent) = self.window_map.remove(&win) {
-            if let Some(index) = self.floating_win_stack.iter().position(|w| *w == parent) {
-                self.floating_win_stack.remove(index);
-            }
-            if let Some(index) = self.tiling_win_stack.iter().position(|w| *w == parent) {
-                self.tiling_win_stack.remove(index);
-            }
-            if let Some(focused) = self.focused {
-                if focused == win {
-                    self.focused = None;
-                }
+            info!("unmanaging {:#x}", win);
+            for workspace in &mut self.workspaces {
+                workspace.remove(parent);
+                workspace.focus_history.retain(|w| *w != win);
             }
+            self.client_list.retain(|w| *w != win);
+            self.pending_kills.remove(&win);
+            self.size_hints.remove(&win);
+            self.always_floating.remove(&win);
+            self.fullscreen.remove(&win);
+            self.borders.remove(&win);
+            self.transient_for.remove(&win);
+            self.transient_for.retain(|_, parent| *parent != win);
 
             self.window_map_reverse.remove(&parent);
             let screen = &self.conn.setup().roots[self.screen_num];
             self.conn.reparent_window(win, screen.root, 0, 0)?;
             self.conn.unmap_window(parent)?;
             self.conn.destroy_window(parent)?;
+            self.update_client_list()?;
+            self.emit(EventKind::Window, json!({ "change": "unmanage", "window": win }));
+            if self.focused == Some(win) {
+                // revert to the most recently focused window that is still around.
+                self.focused = None;
+                self.focus_window(self.workspace().focus_history.last().copied())?;
+            }
             self.conn.flush()?;
         }
         Ok(())
     }
 
+    // a missing or malformed WM_NORMAL_HINTS property does not constrain the window.
+    fn read_size_hints(&self, win: Window) -> Result<SizeHints, ReplyError> {
+        match WmSizeHints::get_normal_hints(&self.conn, win)?.reply() {
+            Ok(hints) => Ok(SizeHints::from(&hints)),
+            Err(ReplyError::ConnectionError(ConnectionError::ParseError(_))) => Ok(SizeHints::default()),
+            Err(err) => Err(err)
+        }
+    }
+
+    fn constrain_size(&self, client: Window, width: u32, height: u32) -> (u32, u32) {
+        match self.size_hints.get(&client) {
+            Some(hints) => hints.constrain(width, height),
+            None => (width, height)
+        }
+    }
+
+    fn handle_property_notify(&mut self, event: PropertyNotifyEvent) -> Result<(), ReplyError> {
+        if self.docks.contains_key(&event.window) &&
+           (event.atom == self.atoms._NET_WM_STRUT_PARTIAL || event.atom == self.atoms._NET_WM_STRUT) {
+            let strut = self.read_strut(event.window)?;
+            self.docks.insert(event.window, strut);
+            self.update_workarea()?;
+            return self.retile_all();
+        }
+        if event.atom != u32::from(AtomEnum::WM_NORMAL_HINTS) {
+            return Ok(());
+        }
+        if let Some(&frame) = self.window_map.get(&event.window) {
+            let hints = self.read_size_hints(event.window)?;
+            self.size_hints.insert(event.window, hints);
+            let workspace = match self.workspace_of(frame) {
+                Some(workspace) => workspace,
+                None => return Ok(())
+            };
+            if self.workspaces[workspace].tiling_win_stack.contains(&frame) {
+                if let Some(monitor) = self.monitor_of(workspace) {
+                    self.create_new_layout(monitor)?;
+                }
+            } else {
+                let geom = self.conn.get_geometry(event.window)?.reply()?;
+                let (width, height) = hints.constrain(geom.width as u32, geom.height as u32);
+                let config = ConfigureWindowAux::new().width(width).height(height);
+                self.conn.configure_window(frame, &config)?;
+                self.conn.configure_window(event.window, &config)?;
+            }
+        }
+        Ok(())
+    }
+
     fn handle_configure_request(&self, event: ConfigureRequestEvent) -> Result<(), ReplyError> {
         self.conn.configure_window(
             event.window,
@@ -156,47 +816,130 @@ impl WM {
         Ok(())
     }
 
-    fn get_layout(&self) -> Box<dyn WindowLayout> {
-        if self.layout_flag {
-            Box::new(FibonacciLayout)
-        } else {
-            Box::new(TreeLayout)
+    // a name that is not registered (anymore) falls back to the first layout.
+    fn get_layout(&self, workspace: usize) -> Option<&dyn WindowLayout> {
+        let name = &self.workspaces[workspace].layout;
+        self.layouts.get(name).or_else(|| self.layouts.names().next().and_then(|n| self.layouts.get(n)))
+    }
+
+    pub fn set_layout(&mut self, name: &str) -> Result<(), ReplyOrIdError> {
+        if !self.layouts.contains(name) {
+            warn!("unknown layout '{}'", name);
+            return Ok(());
         }
+        self.workspace_mut().layout = name.to_string();
+        self.apply_layout()
+    }
+
+    pub fn cycle_layout(&mut self, delta: i32) -> Result<(), ReplyOrIdError> {
+        match self.layouts.cycle(&self.workspace().layout, delta).map(str::to_string) {
+            Some(name) => self.set_layout(&name),
+            None => Ok(())
+        }
+    }
+
+    // the ratio is kept within bounds that leave both columns wider than min_win_width.
+    pub fn change_master_ratio(&mut self, delta: f32) -> Result<(), ReplyOrIdError> {
+        let width = self.monitors[self.current_monitor].rect.width as f32;
+        let min = (self.config.layout.min_win_width as f32 / width).min(0.5);
+        let workspace = self.workspace_mut();
+        workspace.master_ratio = (workspace.master_ratio + delta).clamp(min, 1.0 - min);
+        self.create_new_layout(self.current_monitor)?;
+        Ok(())
+    }
+
+    // the count is only bounded below, the layout caps it at the number of windows it places.
+    pub fn change_master_count(&mut self, delta: i32) -> Result<(), ReplyOrIdError> {
+        let workspace = self.workspace_mut();
+        workspace.master_count = workspace.master_count.saturating_add_signed(delta as isize);
+        self.create_new_layout(self.current_monitor)?;
+        Ok(())
     }
 
     pub fn apply_layout(&mut self) -> Result<(), ReplyOrIdError> {
-        self.tiling_win_stack.append(&mut self.floating_win_stack);
-        self.create_new_layout(&self.get_layout())?;
+        let workspace = self.current_workspace();
+        let (floating, tiling): (Vec<Window>, Vec<Window>) = self.workspaces[workspace].floating_win_stack.iter()
+            .partition(|frame| self.window_map_reverse.get(frame).is_some_and(|w| self.always_floating.contains(w)));
+        let workspace = &mut self.workspaces[workspace];
+        workspace.floating_win_stack = floating;
+        workspace.tiling_win_stack.extend(tiling);
+        let (number, layout) = (self.current_workspace() + 1, self.workspace().layout.clone());
+        debug!("applying layout {} on workspace {}", layout, number);
+        self.emit(EventKind::Layout, json!({ "workspace": number, "layout": layout }));
+        self.create_new_layout(self.current_monitor)?;
         Ok(())
     }
 
-    fn handle_button_press(&mut self, event: ButtonPressEvent) -> Result<(), ReplyError> {
-        self.move_flag = event.detail == MOVE_BUTTON;
-        let state: u16 = event.state.into();
-        let mask: u16 = MOD_MASK.into();
-        if (state & mask) != 0 && (self.move_flag || event.detail == RESIZE_BUTTON) {
-            if let Some(window) = self.window_map.get(&event.event) {
-                let geom = self.conn.get_geometry(*window)?.reply().unwrap();
+    // what was clicked and the client for clicks on windows. clicks on the border of a frame and on
+    // the empty screen are reported on the root window.
+    fn click_target(&self, event: &ButtonPressEvent) -> Option<(MouseTarget, Option<Window>)> {
+        if self.window_map.contains_key(&event.event) {
+            return Some((MouseTarget::Window, Some(event.event)));
+        }
+        if let Some(win) = self.window_map_reverse.get(&event.child) {
+            return Some((MouseTarget::Window, Some(*win)));
+        }
+        if event.child != x11rb::NONE {
+            return None;
+        }
+        let on_bar = (0..self.monitors.len()).map(|m| self.usable_area(m)).any(|area| {
+            let (x, y) = (event.root_x as i32 - area.x as i32, event.root_y as i32 - area.y as i32);
+            (0..area.width as i32).contains(&x) && (0..self.config.top_bar.height as i32).contains(&y)
+        });
+        Some((if on_bar { MouseTarget::Bar } else { MouseTarget::Root }, None))
+    }
+
+    fn handle_button_press(&mut self, event: ButtonPressEvent) -> Result<(), ReplyOrIdError> {
+        if self.config.focus_model == FocusModel::Click && self.window_map.contains_key(&event.event) {
+            self.focus_window(Some(event.event))?;
+        }
+        // passes on clicks that were only intercepted to focus the window.
+        self.conn.allow_events(Allow::REPLAY_POINTER, event.time)?;
+
+        let Some((target, win)) = self.click_target(&event) else {
+            return Ok(());
+        };
+        let Some(action) = self.mousebinds.get(target, event.state, event.detail).cloned() else {
+            return Ok(());
+        };
+        match (action, win) {
+            (MouseAction::Move | MouseAction::Resize, Some(win)) if self.fullscreen.contains_key(&win) => (),
+            (action @ (MouseAction::Move | MouseAction::Resize), Some(win)) => {
+                let frame = self.window_map[&win];
+                let geom = self.conn.get_geometry(frame)?.reply()?;
+                self.move_flag = action == MouseAction::Move;
+                self.drag_button = event.detail;
                 self.window = Some((
-                    event.event,
-                    event.event_x,
-                    event.event_y,
+                    win,
+                    event.root_x - geom.x,
+                    event.root_y - geom.y,
                     geom.x as i32,
                     geom.y as i32,
                     geom.width as i32,
                     geom.height as i32,
                 ));
-                self.conn.configure_window(*window, &ConfigureWindowAux::new()
-                    .stack_mode(StackMode::ABOVE))?;
+                self.raise(win)?;
+                self.update_client_list()?;
                 self.conn.flush()?;
+            },
+            (MouseAction::Move | MouseAction::Resize, None) => (),
+            (MouseAction::Action(action), win) => {
+                // actions on the focused window apply to the one clicked, the others to the clicked monitor.
+                match win {
+                    Some(win) => self.focus_window(Some(win))?,
+                    None => if let Some(monitor) = self.monitors.iter().position(|m| m.contains(event.root_x, event.root_y)) {
+                        self.set_current_monitor(monitor)?;
+                    }
+                }
+                debug!("running '{}' for a click", action);
+                action.execute(self)?;
             }
         }
         Ok(())
     }
 
     fn handle_button_release(&mut self, event: ButtonReleaseEvent) {
-        if ( self.move_flag && event.detail == MOVE_BUTTON) ||
-           (!self.move_flag && event.detail == RESIZE_BUTTON) {
+        if event.detail == self.drag_button {
             self.window = None;
         }
     }
@@ -204,12 +947,14 @@ impl WM {
     pub fn stack_inc(&mut self) -> Result<(), ReplyOrIdError> {
         if let Some(win) = self.focused {
             if let Some(win) = self.window_map.get(&win){
-                if self.tiling_win_stack.contains(&win){
-                    let index = self.tiling_win_stack.iter().position(|&w| w == *win).unwrap();
+                let workspace = self.current_workspace();
+                let stack = &mut self.workspaces[workspace].tiling_win_stack;
+                if let Some(index) = stack.iter().position(|&w| w == *win) {
                     if index > 0 {
-                        self.tiling_win_stack.swap(index, index-1);
-                        self.focused = Some(self.tiling_win_stack[index]);
-                        self.create_new_layout(&self.get_layout())?;
+                        stack.swap(index, index-1);
+                        let neighbour = stack[index];
+                        self.focus_under_pointer(neighbour)?;
+                        self.create_new_layout(self.current_monitor)?;
                     }
                 }
             }
@@ -220,12 +965,14 @@ impl WM {
     pub fn stack_dec(&mut self) -> Result<(), ReplyOrIdError> {
         if let Some(win) = self.focused {
             if let Some(win) = self.window_map.get(&win){
-                if self.tiling_win_stack.contains(&win){
-                    let index = self.tiling_win_stack.iter().position(|&w| w == *win).unwrap();
-                    if index < self.tiling_win_stack.len() - 1 {
-                        self.tiling_win_stack.swap(index, index+1);
-                        self.focused = self.window_map_reverse.get(&self.tiling_win_stack[index]).copied();
-                        self.create_new_layout(&self.get_layout())?;
+                let workspace = self.current_workspace();
+                let stack = &mut self.workspaces[workspace].tiling_win_stack;
+                if let Some(index) = stack.iter().position(|&w| w == *win) {
+                    if index < stack.len() - 1 {
+                        stack.swap(index, index+1);
+                        let neighbour = stack[index];
+                        self.focus_under_pointer(neighbour)?;
+                        self.create_new_layout(self.current_monitor)?;
                     }
                 }
             }
@@ -233,41 +980,497 @@ impl WM {
         Ok(())
     }
 
+    // after swapping tiles the pointer rests above the other window, which takes over the
+    // focus if it follows the mouse.
+    fn focus_under_pointer(&mut self, frame: Window) -> Result<(), ReplyError> {
+        if self.config.focus_model == FocusModel::FollowsMouse {
+            self.focus_window(self.window_map_reverse.get(&frame).copied())?;
+        }
+        Ok(())
+    }
+
+    // gives the input focus to win, or to the root window if there is none.
+    fn focus_window(&mut self, win: Option<Window>) -> Result<(), ReplyError> {
+        let win = win.filter(|w| self.window_map.contains_key(w));
+        if win.is_some() && win == self.focused {
+            return Ok(());
+        }
+        let previous = std::mem::replace(&mut self.focused, win);
+        debug!("focusing {:x?}", win);
+        if let Some(previous) = previous {
+            self.grab_buttons(previous)?;
+        }
+        match win {
+            Some(win) => {
+                let frame = self.window_map[&win];
+                if let Some(workspace) = self.workspace_of(frame) {
+                    let history = &mut self.workspaces[workspace].focus_history;
+                    history.retain(|w| *w != win);
+                    history.push(win);
+                    if let Some(monitor) = self.monitor_of(workspace) {
+                        self.set_current_monitor(monitor)?;
+                    }
+                    if self.get_layout(workspace).is_some_and(|layout| layout.stacks_windows()) {
+                        self.raise(win)?;
+                    }
+                }
+                self.grab_buttons(win)?;
+                if self.accepts_input(win)? {
+                    self.conn.set_input_focus(InputFocus::POINTER_ROOT, win, self.event_time)?;
+                }
+                if self.supports_protocol(win, self.atoms.WM_TAKE_FOCUS)? {
+                    self.send_protocol_message(win, self.atoms.WM_TAKE_FOCUS)?;
+                }
+            },
+            None => {
+                self.conn.set_input_focus(InputFocus::POINTER_ROOT, InputFocus::POINTER_ROOT, self.event_time)?;
+            }
+        }
+        self.update_active_window()?;
+        if previous != win {
+            self.emit(EventKind::Focus, json!({ "window": win }));
+        }
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    // windows without WM_HINTS or without the input hint accept the focus.
+    fn accepts_input(&self, win: Window) -> Result<bool, ReplyError> {
+        match WmHints::get(&self.conn, win)?.reply() {
+            Ok(hints) => Ok(hints.input.unwrap_or(true)),
+            Err(ReplyError::ConnectionError(ConnectionError::ParseError(_))) => Ok(true),
+            Err(err) => Err(err)
+        }
+    }
+
     pub fn kill_focused(&mut self) -> Result<(), ReplyOrIdError> {
         if let Some(focused) = self.focused {
-            self.unmanage(focused)?;
-            self.conn.kill_client(focused)?;
+            self.close_window(focused)?;
         }
         Ok(())
     }
 
-    fn create_new_layout(&mut self, layout: &Box<dyn WindowLayout>) -> Result<(), ReplyError> {
-        let screen = &self.conn.setup().roots[self.screen_num];
-        let children = &self.tiling_win_stack;
-        let geom = self.conn.get_geometry(screen.root)?.reply().unwrap();
-        let layout = layout.layout(Rectangle { x: 0, y: 0, width: geom.width, height: geom.height }, children);
-        for (win, rect) in layout {
-            let config = &ConfigureWindowAux::new()
+    // asks the client to close itself and only kills it if it does not comply in time or
+    // is closed a second time. unmanaging happens once the client actually unmaps.
+    fn close_window(&mut self, win: Window) -> Result<(), ReplyError> {
+        if self.pending_kills.contains_key(&win) || !self.supports_protocol(win, self.atoms.WM_DELETE_WINDOW)? {
+            self.conn.kill_client(win)?;
+        } else {
+            self.send_protocol_message(win, self.atoms.WM_DELETE_WINDOW)?;
+            // capped so that adding it to the current time cannot overflow.
+            let timeout = Duration::from_millis(self.config.close_timeout_ms.min(u32::MAX as u64));
+            self.pending_kills.insert(win, Instant::now() + timeout);
+        }
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn kill_unresponsive(&mut self) -> Result<(), ReplyError> {
+        let now = Instant::now();
+        let expired: Vec<Window> = self.pending_kills.iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(win, _)| *win)
+            .collect();
+        for win in expired {
+            self.pending_kills.remove(&win);
+            self.conn.kill_client(win)?;
+        }
+        Ok(())
+    }
+
+    fn supports_protocol(&self, win: Window, protocol: Atom) -> Result<bool, ReplyError> {
+        let reply = self.conn.get_property(
+            false, win,
+            self.atoms.WM_PROTOCOLS,
+            AtomEnum::ATOM,
+            0, u32::MAX
+        )?.reply()?;
+        Ok(reply.value32().is_some_and(|mut protocols| protocols.any(|p| p == protocol)))
+    }
+
+    fn send_protocol_message(&self, win: Window, protocol: Atom) -> Result<(), ReplyError> {
+        let event = ClientMessageEvent::new(
+            32, win,
+            self.atoms.WM_PROTOCOLS,
+            [protocol, self.event_time, 0, 0, 0]
+        );
+        self.conn.send_event(false, win, EventMask::NO_EVENT, event)?;
+        Ok(())
+    }
+
+    fn activate_window(&mut self, win: Window) -> Result<(), ReplyOrIdError> {
+        if let Some(frame) = self.window_map.get(&win).copied() {
+            if let Some(workspace) = self.workspace_of(frame) {
+                self.switch_workspace(workspace)?;
+            }
+            self.raise(win)?;
+            self.focus_window(Some(win))?;
+            self.update_client_list()?;
+            self.conn.flush()?;
+        }
+        Ok(())
+    }
+
+    fn handle_client_message(&mut self, event: ClientMessageEvent) -> Result<(), ReplyOrIdError> {
+        if event.type_ == self.atoms._NET_ACTIVE_WINDOW {
+            self.activate_window(event.window)?;
+        } else if event.type_ == self.atoms._NET_CLOSE_WINDOW && self.window_map.contains_key(&event.window) {
+            self.close_window(event.window)?;
+        } else if event.type_ == self.atoms._NET_CURRENT_DESKTOP {
+            self.switch_workspace(event.data.as_data32()[0] as usize)?;
+        } else if event.type_ == self.atoms._NET_WM_DESKTOP {
+            self.move_to_workspace(event.window, event.data.as_data32()[0] as usize)?;
+        } else if event.type_ == self.atoms._NET_WM_STATE {
+            // data: the action (remove, add, toggle) followed by up to two properties.
+            let data = event.data.as_data32();
+            if data[1] == self.atoms._NET_WM_STATE_FULLSCREEN || data[2] == self.atoms._NET_WM_STATE_FULLSCREEN {
+                let enable = match data[0] {
+                    0 => false,
+                    1 => true,
+                    _ => !self.fullscreen.contains_key(&event.window)
+                };
+                self.set_fullscreen(event.window, enable)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_ipc_server(&mut self, server: IpcServer) {
+        self.ipc = Some(server);
+    }
+
+    // the server stays in place while the requests run, so the events they cause are not lost.
+    fn handle_ipc(&mut self) {
+        let Some(requests) = self.ipc.as_mut().map(IpcServer::read_requests) else {
+            return;
+        };
+        for (client, request) in requests {
+            let result = match request {
+                Ok(Request::Subscribe(kinds)) => {
+                    if let Some(ipc) = &mut self.ipc {
+                        ipc.subscribe(client, &kinds);
+                    }
+                    Ok(Value::Null)
+                },
+                Ok(request) => self.handle_request(request),
+                Err(err) => Err(err)
+            };
+            if let Some(ipc) = &mut self.ipc {
+                ipc.reply(client, result);
+            }
+        }
+        if let Some(ipc) = &mut self.ipc {
+            for (kind, data) in self.ipc_events.drain(..) {
+                ipc.broadcast(kind, data);
+            }
+        }
+    }
+
+    fn handle_request(&mut self, request: Request) -> Result<Value, String> {
+        match request {
+            Request::Command(action) => action.execute(self)
+                .map(|_| Value::Null)
+                .map_err(|err| err.to_string()),
+            Request::GetTree => self.tree().map_err(|err| err.to_string()),
+            Request::Subscribe(_) => Ok(Value::Null)
+        }
+    }
+
+    fn emit(&mut self, kind: EventKind, data: Value) {
+        if self.ipc.is_some() {
+            self.ipc_events.push((kind, data));
+        }
+    }
+
+    // the monitors, workspaces and windows as seen by the WM, workspaces and monitors are
+    // numbered from 1 like in commands.
+    fn tree(&self) -> Result<Value, ReplyError> {
+        let monitors: Vec<Value> = self.monitors.iter().enumerate()
+            .map(|(i, m)| json!({ "number": i + 1, "rect": rect_json(m.rect), "workspace": m.workspace + 1 }))
+            .collect();
+        let mut workspaces = Vec::new();
+        for (i, workspace) in self.workspaces.iter().enumerate() {
+            workspaces.push(json!({
+                "number": i + 1,
+                "layout": workspace.layout,
+                "master_ratio": workspace.master_ratio,
+                "master_count": workspace.master_count,
+                "tiling_win_stack": self.stack_tree(&workspace.tiling_win_stack)?,
+                "floating_win_stack": self.stack_tree(&workspace.floating_win_stack)?,
+                "focus_history": workspace.focus_history
+            }));
+        }
+        Ok(json!({
+            "focused": self.focused,
+            "current_monitor": self.current_monitor + 1,
+            "monitors": monitors,
+            "workspaces": workspaces
+        }))
+    }
+
+    fn stack_tree(&self, stack: &[Window]) -> Result<Vec<Value>, ReplyError> {
+        let mut windows = Vec::new();
+        for frame in stack {
+            if let Some(&client) = self.window_map_reverse.get(frame) {
+                let geom = self.conn.get_geometry(*frame)?.reply()?;
+                let props = self.window_properties(client)?;
+                windows.push(json!({
+                    "client": client,
+                    "frame": frame,
+                    "class": props.class,
+                    "instance": props.instance,
+                    "title": props.title,
+                    "geometry": rect_json(Rectangle { x: geom.x, y: geom.y, width: geom.width, height: geom.height }),
+                    "fullscreen": self.fullscreen.contains_key(&client)
+                }));
+            }
+        }
+        Ok(windows)
+    }
+
+    pub fn request_reload(&mut self) {
+        self.reload_requested = true;
+    }
+
+    pub fn layouts(&self) -> &LayoutRegistry {
+        &self.layouts
+    }
+
+    pub fn take_reload_request(&mut self) -> bool {
+        std::mem::take(&mut self.reload_requested)
+    }
+
+    pub fn key_mode(&self) -> Option<&str> {
+        self.key_mode.as_deref()
+    }
+
+    // returns whether the mode is active. the keys of a mode only arrive while the keyboard is
+    // grabbed, so the previous mode stays active when another client holds the keyboard.
+    pub fn set_key_mode(&mut self, name: &str) -> Result<bool, ReplyError> {
+        let mode = if name == DEFAULT_MODE {
+            None
+        } else if self.config.modes.contains_key(name) {
+            Some(name.to_string())
+        } else {
+            warn!("unknown keybinding mode '{}'", name);
+            return Ok(false);
+        };
+        if mode == self.key_mode {
+            return Ok(true);
+        }
+        let grabbed = self.keyboard_grabbed();
+        let previous = mem::replace(&mut self.key_mode, mode);
+        if !self.update_keyboard_grab(grabbed)? {
+            self.key_mode = previous;
+            return Ok(false);
+        }
+        debug!("entering keybinding mode {}", name);
+        self.emit(EventKind::Mode, json!({ "mode": name }));
+        Ok(true)
+    }
+
+    // returns whether the chord is shown, like set_key_mode it needs the keyboard grabbed.
+    pub fn set_key_chord(&mut self, keys: Option<String>) -> Result<bool, ReplyError> {
+        let grabbed = self.keyboard_grabbed();
+        let previous = mem::replace(&mut self.key_chord, keys);
+        if !self.update_keyboard_grab(grabbed)? {
+            self.key_chord = previous;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn keyboard_grabbed(&self) -> bool {
+        self.key_mode.is_some() || self.key_chord.is_some()
+    }
+
+    // returns false when the keyboard had to be grabbed but another client holds it.
+    fn update_keyboard_grab(&self, was_grabbed: bool) -> Result<bool, ReplyError> {
+        let root = self.conn.setup().roots[self.screen_num].root;
+        match (was_grabbed, self.keyboard_grabbed()) {
+            (false, true) => {
+                let status = self.conn.grab_keyboard(false, root, x11rb::CURRENT_TIME, GrabMode::ASYNC, GrabMode::ASYNC)?
+                    .reply()?.status;
+                if status != GrabStatus::SUCCESS {
+                    warn!("failed to grab the keyboard, another client holds it: {:?}", status);
+                    return Ok(false);
+                }
+            },
+            (true, false) => { self.conn.ungrab_keyboard(x11rb::CURRENT_TIME)?; },
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    // a config that failed to load or whose keybindings are invalid leaves the previous one active.
+    pub fn reload_config(&mut self, loaded: Result<Config, ConfigError>, key_handler: &mut KeyBindHandler<C>) -> Result<(), ReplyOrIdError> {
+        match loaded {
+            Ok(config) => match KeyBindHandler::from_config(&config) {
+                Ok(handler) => {
+                    info!("reloading the config");
+                    *key_handler = handler;
+                    self.reload(config, key_handler)
+                },
+                Err(err) => {
+                    warn!("invalid keybinding, keeping the previous config: {}", err);
+                    Ok(())
+                }
+            },
+            Err(err) => {
+                warn!("failed to reload config, keeping the previous one: {}", err);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn reload(&mut self, mut config: Config, key_handler: &impl KeyHandler<C>) -> Result<(), ReplyOrIdError> {
+        let root = self.conn.setup().roots[self.screen_num].root;
+        check_default_layout(&mut config, &self.layouts);
+        self.mousebinds = MouseBindings::new(&config);
+        self.config = config;
+        // workspaces are only ever added, removing them would strand their windows.
+        while self.workspaces.len() < self.config.workspaces {
+            self.workspaces.push(Workspace::new(&self.config.layout));
+        }
+        self.conn.change_property32(PropMode::REPLACE, root, self.atoms._NET_NUMBER_OF_DESKTOPS, AtomEnum::CARDINAL, &[self.workspaces.len() as u32])?;
+        self.update_workarea()?;
+        // the modes of the previous config may be gone.
+        self.set_key_chord(None)?;
+        self.set_key_mode(DEFAULT_MODE)?;
+        self.regrab_keys(key_handler)?;
+        self.regrab_buttons()?;
+        for monitor in 0..self.monitors.len() {
+            if monitor != self.current_monitor {
+                self.create_new_layout(monitor)?;
+            }
+        }
+        self.apply_layout()
+    }
+
+    fn create_new_layout(&mut self, monitor: usize) -> Result<(), ReplyError> {
+        let workspace = self.monitors[monitor].workspace;
+        let area = self.layout_area(monitor);
+        // fullscreen windows keep their place in the stack but are left out of the layout.
+        let children: Vec<Window> = self.workspaces[workspace].tiling_win_stack.iter()
+            .filter(|frame| !self.is_fullscreen_frame(**frame))
+            .copied()
+            .collect();
+        let ws = &self.workspaces[workspace];
+        let params = LayoutParams {
+            master_ratio: ws.master_ratio,
+            master_count: ws.master_count,
+            min_width: self.config.layout.min_win_width as u16
+        };
+        let (layout, stacked) = match self.get_layout(workspace) {
+            Some(layout) => (layout.layout(area, &children, &params), layout.stacks_windows()),
+            None => (Vec::new(), false)
+        };
+        for (frame, rect) in layout {
+            // the frame fills the tile, a client with size hints is centered inside it.
+            if let Some(client) = self.window_map_reverse.get(&frame) {
+                let (width, height) = self.constrain_size(*client, rect.width as u32, rect.height as u32);
+                self.conn.configure_window(*client, &ConfigureWindowAux::new()
+                    .x((rect.width as u32).saturating_sub(width) as i32 / 2)
+                    .y((rect.height as u32).saturating_sub(height) as i32 / 2)
+                    .width(width)
+                    .height(height))?;
+            }
+            self.conn.configure_window(frame, &ConfigureWindowAux::new()
+                .x(rect.x as i32)
+                .y(rect.y as i32)
                 .width(rect.width as u32)
-                .height(rect.height as u32);
-            if let Some(parent) = self.window_map_reverse.get(&win) {
-                self.conn.configure_window(*parent, &config)?;
+                .height(rect.height as u32))?;
+        }
+        if stacked {
+            if let Some(win) = self.workspaces[workspace].focus_history.last() {
+                self.raise(*win)?;
             }
-            self.conn.configure_window(win, &config
+        }
+
+        let rect = self.monitors[monitor].rect;
+        let fullscreen: Vec<(Window, Window)> = self.workspaces[workspace].frames()
+            .filter(|frame| self.is_fullscreen_frame(**frame))
+            .filter_map(|frame| self.window_map_reverse.get(frame).map(|client| (*frame, *client)))
+            .collect();
+        for (frame, client) in fullscreen {
+            self.conn.configure_window(frame, &ConfigureWindowAux::new()
                 .x(rect.x as i32)
-                .y(rect.y as i32))?;
+                .y(rect.y as i32)
+                .width(rect.width as u32)
+                .height(rect.height as u32)
+                .border_width(0)
+                .stack_mode(StackMode::ABOVE))?;
+            self.conn.configure_window(client, &ConfigureWindowAux::new()
+                .x(0)
+                .y(0)
+                .width(rect.width as u32)
+                .height(rect.height as u32))?;
         }
         self.draw_top_bar()?;
         self.conn.flush()?;
         Ok(())
     }
 
-    fn handle_key_press(&mut self, event: KeyPressEvent, key_handler: &impl KeyHandler) -> Result<(), ReplyOrIdError> {
+    fn is_fullscreen_frame(&self, frame: Window) -> bool {
+        self.window_map_reverse.get(&frame).is_some_and(|client| self.fullscreen.contains_key(client))
+    }
+
+    pub fn toggle_fullscreen(&mut self) -> Result<(), ReplyOrIdError> {
+        if let Some(win) = self.focused {
+            self.set_fullscreen(win, !self.fullscreen.contains_key(&win))?;
+        }
+        Ok(())
+    }
+
+    // a fullscreen window covers its whole monitor including the top bar, leaving it
+    // restores the previous geometry and position in the layout.
+    fn set_fullscreen(&mut self, win: Window, enable: bool) -> Result<(), ReplyError> {
+        let frame = match self.window_map.get(&win) {
+            Some(frame) => *frame,
+            None => return Ok(())
+        };
+        if enable == self.fullscreen.contains_key(&win) {
+            return Ok(());
+        }
+        // the other states of the client are kept.
+        let mut states: Vec<Atom> = self.window_states(win)?.into_iter()
+            .filter(|state| *state != self.atoms._NET_WM_STATE_FULLSCREEN)
+            .collect();
+        if enable {
+            let geom = self.conn.get_geometry(frame)?.reply()?;
+            self.fullscreen.insert(win, Rectangle { x: geom.x, y: geom.y, width: geom.width, height: geom.height });
+            states.push(self.atoms._NET_WM_STATE_FULLSCREEN);
+            self.conn.change_property32(PropMode::REPLACE, win, self.atoms._NET_WM_STATE, AtomEnum::ATOM, &states)?;
+        } else if let Some(rect) = self.fullscreen.remove(&win) {
+            self.conn.configure_window(frame, &ConfigureWindowAux::new()
+                .x(rect.x as i32)
+                .y(rect.y as i32)
+                .width(rect.width as u32)
+                .height(rect.height as u32)
+                .border_width(self.borders.get(&win).copied().unwrap_or(1) as u32))?;
+            self.conn.configure_window(win, &ConfigureWindowAux::new()
+                .width(rect.width as u32)
+                .height(rect.height as u32))?;
+            self.conn.change_property32(PropMode::REPLACE, win, self.atoms._NET_WM_STATE, AtomEnum::ATOM, &states)?;
+        }
+        if let Some(monitor) = self.workspace_of(frame).and_then(|ws| self.monitor_of(ws)) {
+            self.create_new_layout(monitor)?;
+        }
+        Ok(())
+    }
+
+    fn handle_key_press(&mut self, event: KeyPressEvent, key_handler: &impl KeyHandler<C>) -> Result<(), ReplyOrIdError> {
         key_handler.handle_key_bind(self, event.state, event.detail)?;
         Ok(())
     }
 
-    fn handle_motion_notify(&self, event: MotionNotifyEvent) -> Result<(), ReplyError> {
+    fn handle_motion_notify(&mut self, event: MotionNotifyEvent) -> Result<(), ReplyError> {
+        if self.window.is_none() {
+            // moving across the empty part of a monitor makes it the current one.
+            if let Some(monitor) = self.monitors.iter().position(|m| m.contains(event.root_x, event.root_y)) {
+                self.set_current_monitor(monitor)?;
+            }
+        }
         if let Some((window, x_offset, y_offset, w_x, w_y, width, height)) = self.window {
             let (x, y) = (event.root_x - x_offset, event.root_y - y_offset);
             let (x, y) = (x as i32, y as i32);
@@ -277,88 +1480,156 @@ impl WM {
                     self.conn.configure_window(*parent, &ConfigureWindowAux::new().x(x).y(y))?;
                 }
             } else {
-                let (width, height) = (cmp::max(width + x - w_x, MIN_WIN_WIDTH), cmp::max(height + y - w_y, MIN_WIN_WIDTH));
-                let (width, height) = (width as u32, height as u32);
+                let (width, height) = (cmp::max(width + x - w_x, self.config.layout.min_win_width), cmp::max(height + y - w_y, self.config.layout.min_win_width));
+                let (width, height) = self.constrain_size(window, width as u32, height as u32);
                 let config = ConfigureWindowAux::new().width(width).height(height);
                 // TODO: nicify if statements
                 if let Some(parent) = self.window_map.get(&window) {
                     self.conn.configure_window(*parent, &config)?;
                 }
-                self.conn.configure_window(window, &config)?;
+                // a client centered in its tile moves back to the corner of the frame.
+                self.conn.configure_window(window, &config.x(0).y(0))?;
             };
             self.conn.flush()?;
         }
         Ok(())
     }
 
-    fn handle_enter_notify(&mut self, event: EnterNotifyEvent) {
-        if event.child != 0 {
-            self.focused = Some(event.child);
+    fn handle_enter_notify(&mut self, event: EnterNotifyEvent) -> Result<(), ReplyError> {
+        if self.config.focus_model == FocusModel::FollowsMouse && event.mode == NotifyMode::NORMAL {
+            self.focus_window(self.window_map_reverse.get(&event.event).copied())?;
         }
+        Ok(())
     }
 
-    fn handle_leave_notify(&mut self, _event: LeaveNotifyEvent) {
-        self.focused = None;
+    fn handle_leave_notify(&mut self, event: LeaveNotifyEvent) -> Result<(), ReplyError> {
+        // moving from the frame into the client also counts as leaving the frame.
+        if self.config.focus_model == FocusModel::FollowsMouse &&
+           event.mode == NotifyMode::NORMAL &&
+           event.detail != NotifyDetail::INFERIOR {
+            self.focus_window(None)?;
+        }
+        Ok(())
     }
 
+    // grabs the buttons of the window bindings, their clicks never reach the client.
     pub fn grab_buttons(&self, win: Window) -> Result<(), ReplyError> {
-        self.conn.grab_button(
-            false, win,
-            EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | EventMask::POINTER_MOTION,
-            GrabMode::ASYNC,
-            GrabMode::ASYNC,
-            x11rb::NONE,
-            x11rb::NONE,
-            ButtonIndex::ANY,
-            MOD_MASK,
-        )?;
+        self.conn.ungrab_button(ButtonIndex::ANY, win, ModMask::ANY)?;
+        // with click to focus, any click on an unfocused window is intercepted to focus it.
+        if self.config.focus_model == FocusModel::Click && self.focused != Some(win) {
+            self.conn.grab_button(
+                false, win,
+                EventMask::BUTTON_PRESS,
+                GrabMode::SYNC,
+                GrabMode::SYNC,
+                x11rb::NONE,
+                x11rb::NONE,
+                ButtonIndex::ANY,
+                ModMask::ANY,
+            )?;
+        }
+        for bind in self.mousebinds.window_binds() {
+            for modifiers in grab_masks(*bind) {
+                self.conn.grab_button(
+                    false, win,
+                    EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | EventMask::POINTER_MOTION,
+                    GrabMode::ASYNC,
+                    GrabMode::ASYNC,
+                    x11rb::NONE,
+                    x11rb::NONE,
+                    ButtonIndex::from(bind.button),
+                    modifiers,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn regrab_buttons(&self) -> Result<(), ReplyError> {
+        for win in self.window_map.keys() {
+            self.grab_buttons(*win)?;
+        }
         Ok(())
     }
 
-    pub fn grab_keys(&self, key_handler: &impl KeyHandler, win: Window) -> Result<(), ReplyError> {
+    pub fn grab_keys(&self, key_handler: &impl KeyHandler<C>, win: Window) -> Result<(), ReplyError> {
         key_handler.grab_keys(&self.conn, win)?;
         Ok(())
     }
 
+    // replaces the key grabs on the root and every client, after the bindings or the keyboard mapping changed.
+    fn regrab_keys(&self, key_handler: &impl KeyHandler<C>) -> Result<(), ReplyError> {
+        let root = self.conn.setup().roots[self.screen_num].root;
+        for win in iter::once(root).chain(self.window_map.keys().copied()) {
+            self.conn.ungrab_key(Grab::ANY, win, ModMask::ANY)?;
+            self.grab_keys(key_handler, win)?;
+        }
+        Ok(())
+    }
+
+    // the lock modifiers may have moved, the buttons are grabbed once for each of their states.
+    fn update_keymap(&self, key_handler: &impl KeyHandler<C>) -> Result<(), ReplyError> {
+        info!("the keyboard mapping changed, grabbing the keys and buttons again");
+        key_handler.update_keymap(&self.conn)?;
+        self.regrab_keys(key_handler)?;
+        self.regrab_buttons()
+    }
+
+    fn window_title(&self, win: Window) -> Result<String, ReplyError> {
+        let p = self
+            .conn
+            .get_property(
+                false,
+                win,
+                AtomEnum::WM_NAME,
+                AtomEnum::STRING,
+                0,
+                u32::MAX,
+            )?
+            .reply()?;
+        // titles are not required to be valid UTF-8.
+        Ok(String::from_utf8_lossy(&p.value).into_owned())
+    }
+
     pub fn draw_top_bar(&self) -> Result<(), ReplyError> {
         let root = self.conn.setup().roots[self.screen_num].root;
-        let geom = self.conn.get_geometry(root)?.reply().unwrap();
-        self.conn.change_gc(self.gc, &ChangeGCAux::new().foreground(TOP_BAR_COLOR))?;
-        self.conn.poly_fill_rectangle(root, self.gc, &[
-            Rectangle { x: 0, y: 0, width: geom.width, height: TOP_BAR_HEIGHT },
-        ])?;
-        self.conn.change_gc(self.gc, &ChangeGCAux::new()
-            .foreground(TEXT_COLOR)
-            .background(TOP_BAR_COLOR))?;
+        let (colors, bar) = (&self.config.colors, &self.config.top_bar);
+        for (i, monitor) in self.monitors.iter().enumerate() {
+            let rect = self.usable_area(i);
+            self.conn.change_gc(self.gc, &ChangeGCAux::new().foreground(colors.top_bar))?;
+            self.conn.poly_fill_rectangle(root, self.gc, &[
+                Rectangle { x: rect.x, y: rect.y, width: rect.width, height: bar.height },
+            ])?;
+            self.conn.change_gc(self.gc, &ChangeGCAux::new()
+                .foreground(colors.text)
+                .background(colors.top_bar))?;
 
-        if let Some(win) = self.focused {
-            let p = self
-                .conn
-                .get_property(
-                    false,
-                    win,
-                    AtomEnum::WM_NAME,
-                    AtomEnum::STRING,
-                    0,
-                    u32::MAX,
-                )?
-                .reply()?;
-            let p = p.value;
-            self.conn.image_text8(
-                root,
-                self.gc,
-                TOP_BAR_TEXT_OFFSET,
-                TOP_BAR_HEIGHT as i16 - 4,
-                String::from_utf8(p).unwrap().as_bytes()
-            )?;
-        }
-        else {
+            // only the current monitor shows the focused window, the others their last active one.
+            let win = if i == self.current_monitor {
+                self.focused
+            } else {
+                self.workspaces[monitor.workspace].focus_history.last().copied()
+            };
+            let title = match win.map(|win| self.window_title(win)) {
+                Some(Ok(title)) => title,
+                // the window was destroyed before its unmanage event arrived.
+                Some(Err(ReplyError::X11Error(_))) => String::new(),
+                Some(Err(err)) => return Err(err),
+                None => "MSWM".to_string()
+            };
+            let layout = &self.workspaces[monitor.workspace].layout;
+            let keys = match (&self.key_mode, &self.key_chord) {
+                (Some(mode), Some(chord)) => format!("[{}: {}] ", mode, chord),
+                (Some(keys), None) | (None, Some(keys)) => format!("[{}] ", keys),
+                (None, None) => String::new()
+            };
+            let text = format!("[{}] [{}] {}{}", monitor.workspace + 1, layout, keys, title);
             self.conn.image_text8(
                 root,
                 self.gc,
-                TOP_BAR_TEXT_OFFSET,
-                TOP_BAR_HEIGHT as i16 - 4,
-                "MSWM".as_bytes()
+                rect.x + bar.text_offset,
+                rect.y + bar.height as i16 - 4,
+                text.as_bytes()
             )?;
         }
 
@@ -370,6 +1641,9 @@ impl WM {
         if let Some(seq) = event.wire_sequence_number() {
             while let Some(&Reverse(ignore)) = self.sequence_ignore.peek() {
                 if ignore.wrapping_sub(seq) <= u16::MAX / 2 {
+                    if ignore == seq {
+                        debug!("ignoring event #{} caused by the WM itself: {:?}", seq, event);
+                    }
                     return ignore != seq
                 }
                 self.sequence_ignore.pop();
@@ -378,27 +1652,103 @@ impl WM {
         true
     }
 
-    pub fn handle_events(&mut self, key_handler: &impl KeyHandler) -> Result<(), ReplyOrIdError> {
-        let mut event_opt = Some(self.conn.wait_for_event()?);
+    // blocks until either the X connection or one of the given file descriptors is readable,
+    // or until the next unresponsive client is due to be killed or the given deadline passed.
+    fn wait_for_activity(&self, wake_fds: &[RawFd], deadline: Option<Instant>) -> Result<(), ConnectionError> {
+        let mut fds: Vec<PollFd> = wake_fds.iter()
+            .map(|fd| PollFd::new(*fd, PollFlags::POLLIN))
+            .collect();
+        fds.push(PollFd::new(self.conn.raw_fd(), PollFlags::POLLIN));
+        let now = Instant::now();
+        // rounded up so the deadline has passed on waking up, long timeouts are cut to what poll takes.
+        let timeout = self.pending_kills.values().chain(&deadline).min().map_or(-1, |deadline| {
+            deadline.saturating_duration_since(now).as_millis().min(i32::MAX as u128 - 1) as i32 + 1
+        });
+        match poll(&mut fds, timeout) {
+            Ok(_) | Err(Errno::EINTR) => Ok(()),
+            Err(errno) => Err(io::Error::from(errno).into())
+        }
+    }
+
+    // only returns an error if the session cannot go on, errors caused by a single window are logged.
+    pub fn handle_events(&mut self, key_handler: &impl KeyHandler<C>, wake_fds: &[RawFd]) -> Result<(), Error> {
+        self.conn.flush()?;
+        let mut event_opt = self.conn.poll_for_event()?;
+        if event_opt.is_none() {
+            let mut fds = wake_fds.to_vec();
+            fds.extend(self.ipc.iter().flat_map(|ipc| ipc.fds()));
+            self.wait_for_activity(&fds, key_handler.chord_deadline())?;
+            event_opt = self.conn.poll_for_event()?;
+        }
+        recover(self.kill_unresponsive())?;
+        recover(key_handler.handle_timeout(self))?;
         while let Some(event) = &event_opt {
-            if self.should_execute(&event) {
-                match event {
-                    Event::ConfigureRequest(event) => self.handle_configure_request(*event)?,
-                    Event::ButtonPress(event) => self.handle_button_press(*event)?,
-                    Event::ButtonRelease(event) => self.handle_button_release(*event),
-                    Event::MotionNotify(event) => self.handle_motion_notify(*event)?,
-                    Event::EnterNotify(event) => self.handle_enter_notify(*event),
-                    Event::KeyPress(event) => self.handle_key_press(*event, key_handler)?,
-                    Event::LeaveNotify(event) => self.handle_leave_notify(*event),
-                    Event::MapRequest(event) => self.manage(event.window, key_handler)?,
-                    Event::UnmapNotify(event) => self.unmanage(event.window)?,
-                    _ => {}
-                }
+            if self.should_execute(event) {
+                recover(self.handle_event(event, key_handler))?;
             }
             // check if more events are already available.
             event_opt = self.conn.poll_for_event()?
         }
-        self.draw_top_bar()?;
+        self.handle_ipc();
+        recover(self.draw_top_bar())?;
         Ok(())
     }
+
+    fn handle_event(&mut self, event: &Event, key_handler: &impl KeyHandler<C>) -> Result<(), ReplyOrIdError> {
+        if let Some(time) = event_time(event) {
+            self.event_time = time;
+        }
+        match event {
+            Event::ConfigureRequest(event) => self.handle_configure_request(*event)?,
+            Event::ButtonPress(event) => self.handle_button_press(*event)?,
+            Event::ButtonRelease(event) => self.handle_button_release(*event),
+            Event::MotionNotify(event) => self.handle_motion_notify(*event)?,
+            Event::EnterNotify(event) => self.handle_enter_notify(*event)?,
+            Event::KeyPress(event) => self.handle_key_press(*event, key_handler)?,
+            Event::LeaveNotify(event) => self.handle_leave_notify(*event)?,
+            Event::MapRequest(event) => self.manage(event.window, key_handler)?,
+            Event::UnmapNotify(event) => self.unmanage(event.window)?,
+            Event::DestroyNotify(event) => self.unmanage(event.window)?,
+            Event::ClientMessage(event) => self.handle_client_message(*event)?,
+            Event::PropertyNotify(event) => self.handle_property_notify(*event)?,
+            Event::RandrScreenChangeNotify(_) => self.update_monitors()?,
+            Event::MappingNotify(event) if event.request != Mapping::POINTER => self.update_keymap(key_handler)?,
+            Event::XkbNewKeyboardNotify(_) => self.update_keymap(key_handler)?,
+            // errors of requests without a reply arrive as events.
+            Event::Error(err) => return Err(ReplyOrIdError::X11Error(err.clone())),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn event_time(event: &Event) -> Option<Timestamp> {
+    match event {
+        Event::KeyPress(event) => Some(event.time),
+        Event::KeyRelease(event) => Some(event.time),
+        Event::ButtonPress(event) => Some(event.time),
+        Event::ButtonRelease(event) => Some(event.time),
+        Event::MotionNotify(event) => Some(event.time),
+        Event::EnterNotify(event) => Some(event.time),
+        Event::LeaveNotify(event) => Some(event.time),
+        Event::PropertyNotify(event) => Some(event.time),
+        _ => None
+    }
+}
+
+fn rect_json(rect: Rectangle) -> Value {
+    json!({ "x": rect.x, "y": rect.y, "width": rect.width, "height": rect.height })
+}
+
+// layouts are registered in code, so the configured default can only be checked once the WM knows them.
+fn check_default_layout(config: &mut Config, layouts: &LayoutRegistry) {
+    if !layouts.contains(&config.layout.default) {
+        if let Some(name) = layouts.names().next() {
+            warn!("unknown default layout '{}', using '{}'", config.layout.default, name);
+            config.layout.default = name.to_string();
+        }
+    }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,912 @@
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use x11rb::protocol::Request;
+use x11rb::x11_utils::TryParse;
+
+use super::*;
+use crate::fake_connection::{FakeConnection, ROOT};
+use crate::keybind::{init_keymap, KeyBindHandler};
+use crate::logging::TracingConnection;
+
+fn rect(x: i16, y: i16, width: u16, height: u16) -> Rectangle {
+    Rectangle { x, y, width, height }
+}
+
+fn start_wm() -> (WM<FakeConnection>, KeyBindHandler<FakeConnection>) {
+    start_wm_with(Config::default())
+}
+
+fn start_wm_with(config: Config) -> (WM<FakeConnection>, KeyBindHandler<FakeConnection>) {
+    start_wm_on(FakeConnection::new(1000, 800), config)
+}
+
+fn start_wm_on(conn: FakeConnection, config: Config) -> (WM<FakeConnection>, KeyBindHandler<FakeConnection>) {
+    init_keymap(&conn).unwrap();
+    let key_handler = KeyBindHandler::from_config(&config).unwrap();
+    let wm = WM::create_wm(conn, 0, config, LayoutRegistry::default(), &key_handler).unwrap();
+    (wm, key_handler)
+}
+
+// maps new clients and waits until the WM managed them.
+fn map_clients(wm: &mut WM<FakeConnection>, key_handler: &KeyBindHandler<FakeConnection>, count: usize) -> Vec<Window> {
+    let clients: Vec<Window> = (0..count).map(|_| wm.conn.create_client(rect(0, 0, 300, 200))).collect();
+    for client in &clients {
+        wm.conn.map_request(*client);
+    }
+    wm.handle_events(key_handler, &[]).unwrap();
+    clients
+}
+
+fn configured_windows(requests: &[Request<'_>]) -> Vec<Window> {
+    requests.iter().filter_map(|r| match r {
+        Request::ConfigureWindow(r) => Some(r.window),
+        _ => None
+    }).collect()
+}
+
+fn set_protocols(wm: &WM<FakeConnection>, win: Window, protocols: &[Atom]) {
+    let data: Vec<u8> = protocols.iter().flat_map(|p| p.to_ne_bytes()).collect();
+    wm.conn.set_property(win, wm.atoms.WM_PROTOCOLS, AtomEnum::ATOM.into(), 32, &data);
+}
+
+// the WM_PROTOCOLS messages sent to win, as (protocol, time).
+fn protocol_messages(wm: &WM<FakeConnection>, requests: &[Request<'_>], win: Window) -> Vec<(Atom, u32)> {
+    requests.iter().filter_map(|r| match r {
+        Request::SendEvent(r) if r.destination == win => {
+            let (event, _) = ClientMessageEvent::try_parse(&r.event[..]).unwrap();
+            let data = event.data.as_data32();
+            (event.type_ == wm.atoms.WM_PROTOCOLS).then_some((data[0], data[1]))
+        },
+        _ => None
+    }).collect()
+}
+
+fn killed_clients(requests: &[Request<'_>]) -> Vec<u32> {
+    requests.iter().filter_map(|r| match r {
+        Request::KillClient(r) => Some(r.resource),
+        _ => None
+    }).collect()
+}
+
+#[test]
+fn map_request_reparents_the_client_into_a_frame() {
+    let (mut wm, key_handler) = start_wm();
+    let clients = map_clients(&mut wm, &key_handler, 1);
+
+    let frame = wm.window_map[&clients[0]];
+    assert_eq!(wm.conn.window(clients[0]).unwrap().parent, frame);
+    assert!(wm.conn.window(clients[0]).unwrap().mapped);
+    assert!(wm.conn.window(frame).unwrap().mapped);
+    assert_eq!(wm.conn.window(frame).unwrap().parent, ROOT);
+    let client_list = wm.conn.window(ROOT).unwrap().properties[&wm.atoms._NET_CLIENT_LIST].2.clone();
+    assert_eq!(client_list, clients[0].to_ne_bytes());
+}
+
+#[test]
+fn stack_inc_swaps_the_focused_tile_with_the_previous_one() {
+    let (mut wm, key_handler) = start_wm();
+    let clients = map_clients(&mut wm, &key_handler, 3);
+    wm.apply_layout().unwrap();
+    let frames: Vec<Window> = clients.iter().map(|c| wm.window_map[c]).collect();
+    assert_eq!(wm.workspace().tiling_win_stack, frames);
+
+    wm.conn.enter_notify(frames[1]);
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.focused, Some(clients[1]));
+    let before: Vec<Rectangle> = frames.iter().map(|f| wm.conn.window(*f).unwrap().rect).collect();
+    wm.conn.take_requests();
+
+    wm.conn.key_press(KeyButMask::MOD4, "j");
+    wm.handle_events(&key_handler, &[]).unwrap();
+
+    assert_eq!(wm.workspace().tiling_win_stack, vec![frames[1], frames[0], frames[2]]);
+    let configured = configured_windows(&wm.conn.take_requests());
+    assert!(configured.contains(&frames[0]) && configured.contains(&frames[1]));
+    assert_eq!(wm.conn.window(frames[1]).unwrap().rect, before[0]);
+    assert_eq!(wm.conn.window(frames[0]).unwrap().rect, before[1]);
+    assert_eq!(wm.conn.window(frames[2]).unwrap().rect, before[2]);
+    // the pointer now rests above the first client, which takes over the focus.
+    assert_eq!(wm.focused, Some(clients[0]));
+}
+
+#[test]
+fn the_monocle_layout_raises_the_focused_window() {
+    let (mut wm, key_handler) = start_wm();
+    let clients = map_clients(&mut wm, &key_handler, 2);
+    let frames: Vec<Window> = clients.iter().map(|c| wm.window_map[c]).collect();
+    let raised = |requests: &[Request<'_>]| -> Vec<Window> {
+        requests.iter().filter_map(|r| match r {
+            Request::ConfigureWindow(r) if r.value_list.stack_mode == Some(StackMode::ABOVE) => Some(r.window),
+            _ => None
+        }).collect()
+    };
+    wm.focus_window(Some(clients[0])).unwrap();
+    wm.conn.take_requests();
+    wm.focus_window(Some(clients[1])).unwrap();
+    assert!(raised(&wm.conn.take_requests()).is_empty());
+
+    wm.set_layout("monocle").unwrap();
+    assert_eq!(raised(&wm.conn.take_requests()), vec![frames[1]]);
+    wm.focus_window(Some(clients[0])).unwrap();
+    assert_eq!(raised(&wm.conn.take_requests()), vec![frames[0]]);
+}
+
+#[test]
+fn master_ratio_and_count_stay_in_bounds() {
+    let (mut wm, key_handler) = start_wm();
+    map_clients(&mut wm, &key_handler, 2);
+    wm.set_layout("master").unwrap();
+
+    wm.change_master_ratio(-2.0).unwrap();
+    assert!((wm.workspace().master_ratio - 0.01).abs() < 0.001);
+    wm.change_master_ratio(5.0).unwrap();
+    assert!((wm.workspace().master_ratio - 0.99).abs() < 0.001);
+
+    wm.change_master_count(-5).unwrap();
+    assert_eq!(wm.workspace().master_count, 0);
+    // a count above the number of windows is kept, so the next window joins the masters.
+    wm.change_master_count(3).unwrap();
+    assert_eq!(wm.workspace().master_count, 3);
+    let clients = map_clients(&mut wm, &key_handler, 1);
+    wm.apply_layout().unwrap();
+    let frame = wm.conn.window(wm.window_map[&clients[0]]).unwrap().rect;
+    assert_eq!((frame.x, frame.width), (0, 1000));
+}
+
+#[test]
+fn unbound_keys_do_nothing() {
+    let (mut wm, key_handler) = start_wm();
+    map_clients(&mut wm, &key_handler, 2);
+    wm.apply_layout().unwrap();
+    let stack = wm.workspace().tiling_win_stack.clone();
+    wm.conn.take_requests();
+
+    wm.conn.key_press(KeyButMask::MOD4 | KeyButMask::CONTROL, "j");
+    wm.handle_events(&key_handler, &[]).unwrap();
+
+    assert_eq!(wm.workspace().tiling_win_stack, stack);
+    assert!(configured_windows(&wm.conn.take_requests()).is_empty());
+}
+
+#[test]
+fn switching_workspaces_hides_the_frames_of_the_previous_one() {
+    let (mut wm, key_handler) = start_wm();
+    let clients = map_clients(&mut wm, &key_handler, 2);
+    let frames: Vec<Window> = clients.iter().map(|c| wm.window_map[c]).collect();
+
+    wm.conn.key_press(KeyButMask::MOD4, "2");
+    wm.handle_events(&key_handler, &[]).unwrap();
+
+    assert_eq!(wm.current_workspace(), 1);
+    assert!(frames.iter().all(|f| !wm.conn.window(*f).unwrap().mapped));
+    let desktop = &wm.conn.window(ROOT).unwrap().properties[&wm.atoms._NET_CURRENT_DESKTOP].2;
+    assert_eq!(desktop, &1u32.to_ne_bytes());
+
+    wm.conn.key_press(KeyButMask::MOD4, "1");
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert!(frames.iter().all(|f| wm.conn.window(*f).unwrap().mapped));
+}
+
+#[test]
+fn transient_windows_stay_floating() {
+    let (mut wm, key_handler) = start_wm();
+    let parent = map_clients(&mut wm, &key_handler, 1)[0];
+    let dialog = wm.conn.create_client(rect(0, 0, 200, 100));
+    wm.conn.set_property(dialog, AtomEnum::WM_TRANSIENT_FOR.into(), AtomEnum::WINDOW.into(), 32, &parent.to_ne_bytes());
+    wm.conn.map_request(dialog);
+    wm.handle_events(&key_handler, &[]).unwrap();
+
+    let (parent_frame, dialog_frame) = (wm.window_map[&parent], wm.window_map[&dialog]);
+    // dialogs are centered above their parent.
+    let (p, d) = (wm.conn.window(parent_frame).unwrap().rect, wm.conn.window(dialog_frame).unwrap().rect);
+    assert_eq!((d.x, d.y), (p.x + (p.width as i16 - 200) / 2, p.y + (p.height as i16 - 100) / 2));
+
+    wm.apply_layout().unwrap();
+    assert_eq!(wm.workspace().tiling_win_stack, vec![parent_frame]);
+    assert_eq!(wm.workspace().floating_win_stack, vec![dialog_frame]);
+}
+
+#[test]
+fn dialog_and_splash_windows_float() {
+    let (mut wm, key_handler) = start_wm();
+    let types = [wm.atoms._NET_WM_WINDOW_TYPE_DIALOG, wm.atoms._NET_WM_WINDOW_TYPE_SPLASH, AtomEnum::NONE.into()];
+    let clients: Vec<Window> = types.iter().map(|t| {
+        let win = wm.conn.create_client(rect(0, 0, 200, 100));
+        wm.conn.set_property(win, wm.atoms._NET_WM_WINDOW_TYPE, AtomEnum::ATOM.into(), 32, &t.to_ne_bytes());
+        wm.conn.map_request(win);
+        win
+    }).collect();
+    wm.handle_events(&key_handler, &[]).unwrap();
+    wm.apply_layout().unwrap();
+
+    let frames: Vec<Window> = clients.iter().map(|c| wm.window_map[c]).collect();
+    assert_eq!(wm.workspace().floating_win_stack, frames[..2]);
+    assert_eq!(wm.workspace().tiling_win_stack, frames[2..]);
+    // floating windows are centered on the monitor, below the top bar.
+    let bar = wm.config.top_bar.height as i16;
+    let d = wm.conn.window(frames[0]).unwrap().rect;
+    assert_eq!((d.x, d.y), ((1000 - 200) / 2, bar + (800 - bar - 100) / 2));
+}
+
+#[test]
+fn centering_windows_larger_than_the_screen_does_not_overflow() {
+    let (mut wm, key_handler) = start_wm();
+    let win = wm.conn.create_client(rect(0, 0, 200, 100));
+    // WM_SIZE_HINTS with only the minimum size (flag 16) set to 100000x100000.
+    let mut hints = [0u32; 18];
+    hints[0] = 16;
+    hints[5] = 100_000;
+    hints[6] = 100_000;
+    let hints: Vec<u8> = hints.iter().flat_map(|v| v.to_ne_bytes()).collect();
+    wm.conn.set_property(win, AtomEnum::WM_NORMAL_HINTS.into(), AtomEnum::WM_SIZE_HINTS.into(), 32, &hints);
+    wm.conn.set_property(win, wm.atoms._NET_WM_WINDOW_TYPE, AtomEnum::ATOM.into(), 32, &wm.atoms._NET_WM_WINDOW_TYPE_DIALOG.to_ne_bytes());
+    wm.conn.map_request(win);
+    wm.handle_events(&key_handler, &[]).unwrap();
+
+    let frame = wm.conn.window(wm.window_map[&win]).unwrap().rect;
+    assert_eq!((frame.x, frame.y), (i16::MIN, i16::MIN));
+}
+
+#[test]
+fn windows_transient_for_each_other_are_not_raised_forever() {
+    let (mut wm, key_handler) = start_wm();
+    let (a, b) = (wm.conn.create_client(rect(0, 0, 200, 100)), wm.conn.create_client(rect(0, 0, 200, 100)));
+    wm.conn.set_property(a, AtomEnum::WM_TRANSIENT_FOR.into(), AtomEnum::WINDOW.into(), 32, &b.to_ne_bytes());
+    wm.conn.set_property(b, AtomEnum::WM_TRANSIENT_FOR.into(), AtomEnum::WINDOW.into(), 32, &a.to_ne_bytes());
+    wm.conn.map_request(a);
+    wm.conn.map_request(b);
+    wm.handle_events(&key_handler, &[]).unwrap();
+
+    assert_eq!(wm.transient_for.get(&a), Some(&b));
+    assert_eq!(wm.transient_for.get(&b), None);
+    wm.raise(a).unwrap();
+    wm.raise(b).unwrap();
+}
+
+#[test]
+fn windows_that_vanished_before_being_managed_are_skipped() {
+    let (mut wm, key_handler) = start_wm();
+    let clients = map_clients(&mut wm, &key_handler, 1);
+    // a window id the server does not know, as if the client destroyed it right after mapping.
+    wm.conn.map_request(0x4fffff);
+    wm.handle_events(&key_handler, &[]).unwrap();
+
+    assert_eq!(wm.client_list, clients);
+    assert!(!wm.window_map.contains_key(&0x4fffff));
+}
+
+#[test]
+fn titles_do_not_have_to_be_utf8() {
+    let (mut wm, key_handler) = start_wm();
+    let win = wm.conn.create_client(rect(0, 0, 300, 200));
+    wm.conn.set_property(win, AtomEnum::WM_NAME.into(), AtomEnum::STRING.into(), 8, &[0x66, 0xff, 0xfe]);
+    wm.conn.map_request(win);
+    wm.handle_events(&key_handler, &[]).unwrap();
+    wm.conn.enter_notify(wm.window_map[&win]);
+    wm.handle_events(&key_handler, &[]).unwrap();
+
+    assert_eq!(wm.focused, Some(win));
+    assert_eq!(wm.window_title(win).unwrap(), "f\u{fffd}\u{fffd}");
+}
+
+#[test]
+fn the_tracing_connection_passes_replies_through() {
+    let conn = TracingConnection::new(FakeConnection::new(1000, 800));
+    init_keymap(&conn).unwrap();
+    let key_handler = KeyBindHandler::new(HashMap::new()).unwrap();
+    let mut wm = WM::create_wm(conn, 0, Config::default(), LayoutRegistry::default(), &key_handler).unwrap();
+    let win = wm.conn.0.create_client(rect(0, 0, 300, 200));
+    wm.conn.0.map_request(win);
+    wm.handle_events(&key_handler, &[]).unwrap();
+
+    let frame = wm.window_map[&win];
+    assert_eq!(wm.conn.0.window(win).unwrap().parent, frame);
+}
+
+#[test]
+fn keybinds_follow_a_changed_keyboard_mapping() {
+    let (mut wm, key_handler) = start_wm();
+    let clients = map_clients(&mut wm, &key_handler, 2);
+    wm.apply_layout().unwrap();
+    let frames: Vec<Window> = clients.iter().map(|c| wm.window_map[c]).collect();
+    wm.conn.enter_notify(frames[1]);
+    wm.handle_events(&key_handler, &[]).unwrap();
+    wm.conn.take_requests();
+
+    wm.conn.swap_keys("j", "z");
+    wm.handle_events(&key_handler, &[]).unwrap();
+    let requests = wm.conn.take_requests();
+    let grabbed = |win| requests.iter().any(|r| matches!(r,
+        Request::GrabKey(g) if g.grab_window == win && g.key == wm.conn.keycode("j") && g.modifiers == ModMask::M4));
+    assert!(grabbed(ROOT) && grabbed(clients[0]) && grabbed(clients[1]));
+    // the buttons are grabbed for the new state of the lock keys as well.
+    let buttons_grabbed = |win| requests.iter().any(|r| matches!(r,
+        Request::GrabButton(g) if g.grab_window == win && g.modifiers == ModMask::M4 | ModMask::M2));
+    assert!(buttons_grabbed(clients[0]) && buttons_grabbed(clients[1]));
+
+    wm.conn.key_press(KeyButMask::MOD4, "j");
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.workspace().tiling_win_stack, vec![frames[1], frames[0]]);
+}
+
+#[test]
+fn lock_modifiers_are_ignored() {
+    let (mut wm, key_handler) = start_wm();
+    wm.conn.take_requests();
+    let clients = map_clients(&mut wm, &key_handler, 1);
+    // M4+j is grabbed with and without Caps Lock (LOCK) and Num Lock (M2).
+    let requests = wm.conn.take_requests();
+    let mut grabbed: Vec<ModMask> = requests.iter().filter_map(|r| match r {
+        Request::GrabKey(g) if g.grab_window == clients[0] && g.key == wm.conn.keycode("j") => Some(g.modifiers),
+        _ => None
+    }).collect();
+    grabbed.sort_by_key(|m| u16::from(*m));
+    assert_eq!(grabbed, vec![ModMask::M4, ModMask::M4 | ModMask::LOCK, ModMask::M4 | ModMask::M2, ModMask::M4 | ModMask::LOCK | ModMask::M2]);
+
+    wm.conn.key_press(KeyButMask::MOD4 | KeyButMask::MOD2 | KeyButMask::LOCK | KeyButMask::BUTTON1, "2");
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.current_workspace(), 1);
+}
+
+#[test]
+fn chords_grab_the_keyboard_until_they_are_complete() {
+    let mut config = Config::default();
+    config.keybinds.insert("M4+a x".to_string(), "workspace 3".parse().unwrap());
+    let (mut wm, key_handler) = start_wm_with(config);
+
+    wm.conn.key_press(KeyButMask::MOD4, "a");
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.conn.keyboard_grab(), Some(ROOT));
+    assert_eq!(wm.key_chord.as_deref(), Some("M4+a"));
+
+    wm.conn.key_press(KeyButMask::default(), "x");
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.current_workspace(), 2);
+    assert_eq!(wm.conn.keyboard_grab(), None);
+    assert_eq!(wm.key_chord, None);
+}
+
+#[test]
+fn half_typed_chords_time_out() {
+    let mut config = Config::default();
+    config.keybinds.insert("M4+a x".to_string(), "workspace 3".parse().unwrap());
+    config.chord_timeout_ms = 0;
+    let (mut wm, key_handler) = start_wm_with(config);
+
+    wm.conn.key_press(KeyButMask::MOD4, "a");
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.conn.keyboard_grab(), Some(ROOT));
+    // no events arrive, the WM only wakes up for the deadline.
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.conn.keyboard_grab(), None);
+
+    wm.conn.key_press(KeyButMask::default(), "x");
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.current_workspace(), 0);
+}
+
+#[test]
+fn modes_rebind_keys_until_escape() {
+    let (mut wm, key_handler) = start_wm();
+    let ratio = wm.workspace().master_ratio;
+
+    wm.conn.key_press(KeyButMask::MOD4, "r");
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.key_mode(), Some("resize"));
+    assert_eq!(wm.conn.keyboard_grab(), Some(ROOT));
+
+    wm.conn.key_press(KeyButMask::default(), "l");
+    wm.conn.key_press(KeyButMask::default(), "l");
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert!((wm.workspace().master_ratio - (ratio + 0.1)).abs() < 0.001);
+
+    wm.conn.key_press(KeyButMask::default(), "Escape");
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.key_mode(), None);
+    assert_eq!(wm.conn.keyboard_grab(), None);
+}
+
+#[test]
+fn keys_owned_by_other_clients_are_skipped() {
+    let conn = FakeConnection::new(1000, 800);
+    conn.grab_key_elsewhere(ROOT, ModMask::M4, "j");
+    let (mut wm, key_handler) = start_wm_on(conn, Config::default());
+
+    wm.conn.key_press(KeyButMask::MOD4, "2");
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.current_workspace(), 1);
+}
+
+#[test]
+fn scrolling_on_the_top_bar_cycles_workspaces() {
+    let (mut wm, key_handler) = start_wm();
+
+    wm.conn.button_press(ROOT, x11rb::NONE, KeyButMask::default(), 5, 500, 5);
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.current_workspace(), 1);
+
+    wm.conn.button_press(ROOT, x11rb::NONE, KeyButMask::default(), 4, 500, 5);
+    wm.conn.button_press(ROOT, x11rb::NONE, KeyButMask::default(), 4, 500, 5);
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.current_workspace(), 8);
+
+    // below the bar the scroll wheel is not bound.
+    wm.conn.button_press(ROOT, x11rb::NONE, KeyButMask::default(), 5, 500, 300);
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.current_workspace(), 8);
+}
+
+#[test]
+fn dragging_with_the_mod_key_moves_the_window() {
+    let (mut wm, key_handler) = start_wm();
+    let clients = map_clients(&mut wm, &key_handler, 1);
+    let frame = wm.window_map[&clients[0]];
+    let before = wm.conn.window(frame).unwrap().rect;
+
+    wm.conn.button_press(clients[0], x11rb::NONE, KeyButMask::MOD4 | KeyButMask::MOD2, 1, 100, 100);
+    wm.conn.motion_notify(clients[0], 150, 120);
+    wm.conn.button_release(clients[0], 1);
+    wm.conn.motion_notify(clients[0], 300, 300);
+    wm.handle_events(&key_handler, &[]).unwrap();
+
+    let after = wm.conn.window(frame).unwrap().rect;
+    assert_eq!((after.x, after.y), (before.x + 50, before.y + 20));
+}
+
+#[test]
+fn mouse_bindings_replace_the_default_ones() {
+    let config: Config = toml::from_str("[mousebinds.window]\n\"M4+middle\" = \"toggle_fullscreen\"").unwrap();
+    let (mut wm, key_handler) = start_wm_with(config);
+    wm.conn.take_requests();
+    let clients = map_clients(&mut wm, &key_handler, 2);
+
+    let requests = wm.conn.take_requests();
+    let grabbed: Vec<(Button, ModMask)> = requests.iter().filter_map(|r| match r {
+        Request::GrabButton(g) if g.grab_window == clients[0] => Some((u8::from(g.button), g.modifiers)),
+        _ => None
+    }).collect();
+    assert!(grabbed.contains(&(2, ModMask::M4)) && grabbed.contains(&(2, ModMask::M4 | ModMask::M2)));
+    assert!(grabbed.iter().all(|(button, _)| *button == 2));
+
+    wm.conn.button_press(clients[1], x11rb::NONE, KeyButMask::MOD4, 2, 100, 100);
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.focused, Some(clients[1]));
+    assert!(wm.fullscreen.contains_key(&clients[1]));
+
+    // moving is no longer bound.
+    let frame = wm.window_map[&clients[0]];
+    let before = wm.conn.window(frame).unwrap().rect;
+    wm.conn.button_press(clients[0], x11rb::NONE, KeyButMask::MOD4, 1, 100, 100);
+    wm.conn.motion_notify(clients[0], 150, 120);
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.conn.window(frame).unwrap().rect, before);
+}
+
+// serves IPC for the WM on a socket of its own, clients get replies within a second.
+fn start_ipc(wm: &mut WM<FakeConnection>, name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("mswm-test-{}-{}", std::process::id(), name)).join("mswm.sock");
+    wm.set_ipc_server(IpcServer::bind(&path).unwrap());
+    path
+}
+
+fn connect_ipc(path: &std::path::Path, requests: &str) -> BufReader<UnixStream> {
+    let mut stream = UnixStream::connect(path).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    stream.write_all(requests.as_bytes()).unwrap();
+    BufReader::new(stream)
+}
+
+// the next line the client received, or None once nothing arrives in time.
+fn read_ipc(client: &mut BufReader<UnixStream>) -> Option<Value> {
+    let mut line = String::new();
+    match client.read_line(&mut line) {
+        Ok(n) if n > 0 => Some(serde_json::from_str(&line).unwrap()),
+        _ => None
+    }
+}
+
+#[test]
+fn commands_over_ipc_notify_subscribers() {
+    let (mut wm, key_handler) = start_wm();
+    let path = start_ipc(&mut wm, "commands");
+    let mut client = connect_ipc(&path, "{\"subscribe\": [\"workspace\"]}\n{\"command\": \"workspace 2\"}\n");
+    wm.handle_events(&key_handler, &[]).unwrap();
+
+    assert_eq!(read_ipc(&mut client), Some(json!({ "success": true })));
+    assert_eq!(read_ipc(&mut client), Some(json!({ "success": true })));
+    assert_eq!(read_ipc(&mut client), Some(json!({ "event": "workspace", "workspace": 2, "monitor": 1 })));
+}
+
+#[test]
+fn events_only_reach_clients_subscribed_to_them() {
+    let (mut wm, key_handler) = start_wm();
+    let path = start_ipc(&mut wm, "events");
+    let mut windows = connect_ipc(&path, "{\"subscribe\": [\"window\"]}\n");
+    let mut workspaces = connect_ipc(&path, "{\"subscribe\": [\"workspace\"]}\n");
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(read_ipc(&mut windows), Some(json!({ "success": true })));
+    assert_eq!(read_ipc(&mut workspaces), Some(json!({ "success": true })));
+
+    let client = map_clients(&mut wm, &key_handler, 1)[0];
+    let event = read_ipc(&mut windows).unwrap();
+    assert_eq!((&event["event"], &event["change"], &event["window"]), (&json!("window"), &json!("manage"), &json!(client)));
+    assert_eq!(read_ipc(&mut workspaces), None);
+}
+
+#[test]
+fn get_tree_describes_monitors_workspaces_and_windows() {
+    let (mut wm, key_handler) = start_wm();
+    let clients = map_clients(&mut wm, &key_handler, 2);
+    wm.apply_layout().unwrap();
+    let path = start_ipc(&mut wm, "tree");
+    let mut client = connect_ipc(&path, "\"get_tree\"\n");
+    wm.handle_events(&key_handler, &[]).unwrap();
+
+    let reply = read_ipc(&mut client).unwrap();
+    assert_eq!(reply["success"], true);
+    let tree = &reply["data"];
+    assert_eq!(tree["current_monitor"], 1);
+    assert_eq!(tree["monitors"], json!([{ "number": 1, "rect": rect_json(rect(0, 0, 1000, 800)), "workspace": 1 }]));
+    assert_eq!(tree["workspaces"].as_array().unwrap().len(), 9);
+    let stack = tree["workspaces"][0]["tiling_win_stack"].as_array().unwrap();
+    let listed: Vec<&Value> = stack.iter().map(|w| &w["client"]).collect();
+    assert_eq!(listed, vec![&json!(clients[0]), &json!(clients[1])]);
+    assert_eq!(stack[0]["frame"], json!(wm.window_map[&clients[0]]));
+    assert_eq!(tree["workspaces"][1]["tiling_win_stack"], json!([]));
+}
+
+#[test]
+fn modes_are_not_entered_while_another_client_holds_the_keyboard() {
+    let mut config = Config::default();
+    config.keybinds.insert("M4+a x".to_string(), "workspace 3".parse().unwrap());
+    let (mut wm, key_handler) = start_wm_with(config);
+    wm.conn.grab_keyboard_elsewhere(0x1234);
+
+    wm.conn.key_press(KeyButMask::MOD4, "r");
+    wm.conn.key_press(KeyButMask::MOD4, "a");
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.key_mode(), None);
+    assert_eq!(wm.key_chord, None);
+
+    // the keys of the default mode keep working.
+    wm.conn.key_press(KeyButMask::MOD4, "2");
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.current_workspace(), 1);
+}
+
+#[test]
+fn clients_asking_for_fullscreen_before_mapping_start_fullscreen() {
+    let (mut wm, key_handler) = start_wm();
+    let client = wm.conn.create_client(rect(0, 0, 300, 200));
+    let (above, fullscreen) = (0x1234u32, wm.atoms._NET_WM_STATE_FULLSCREEN);
+    let states: Vec<u8> = [above, fullscreen].iter().flat_map(|a| a.to_ne_bytes()).collect();
+    wm.conn.set_property(client, wm.atoms._NET_WM_STATE, AtomEnum::ATOM.into(), 32, &states);
+    wm.conn.map_request(client);
+    wm.handle_events(&key_handler, &[]).unwrap();
+
+    assert!(wm.fullscreen.contains_key(&client));
+    assert_eq!(wm.conn.window(wm.window_map[&client]).unwrap().rect, rect(0, 0, 1000, 800));
+
+    wm.focus_window(Some(client)).unwrap();
+    wm.toggle_fullscreen().unwrap();
+    assert!(!wm.fullscreen.contains_key(&client));
+    let states = wm.conn.window(client).unwrap().properties[&wm.atoms._NET_WM_STATE].2.clone();
+    assert_eq!(states, above.to_ne_bytes());
+}
+
+#[test]
+fn windows_of_a_removed_monitor_move_to_a_remaining_one() {
+    let (mut wm, key_handler) = start_wm();
+    let client = map_clients(&mut wm, &key_handler, 1)[0];
+    let frame = wm.window_map[&client];
+    wm.conn.configure_window(frame, &ConfigureWindowAux::new().x(-900).y(100)).unwrap();
+    // the fake screen only reports the right one of two monitors, as if the left output was unplugged.
+    wm.monitors = vec![
+        Monitor { rect: rect(-1000, 0, 1000, 800), workspace: 0 },
+        Monitor { rect: rect(0, 0, 1000, 800), workspace: 1 }
+    ];
+    wm.current_monitor = 1;
+    wm.update_monitors().unwrap();
+
+    assert_eq!(wm.monitors.len(), 1);
+    assert_eq!((wm.monitors[0].workspace, wm.current_monitor), (1, 0));
+    assert_eq!(wm.workspace_of(frame), Some(1));
+    let r = wm.conn.window(frame).unwrap().rect;
+    assert_eq!((r.x, r.y), (100, 100));
+}
+
+#[test]
+fn rule_positions_far_off_the_screen_do_not_overflow() {
+    let config: Config = toml::from_str("[[rules]]\nclass = \"*\"\ngeometry = \"100x100+32767+32767\"").unwrap();
+    let (mut wm, key_handler) = start_wm_with(config);
+    let client = map_clients(&mut wm, &key_handler, 1)[0];
+
+    let r = wm.conn.window(wm.window_map[&client]).unwrap().rect;
+    assert_eq!((r.x, r.y), (i16::MAX, i16::MAX));
+}
+
+fn input_focus_requests(requests: &[Request<'_>]) -> Vec<(Window, u32)> {
+    requests.iter().filter_map(|r| match r {
+        Request::SetInputFocus(r) => Some((r.focus, r.time)),
+        _ => None
+    }).collect()
+}
+
+#[test]
+fn focusing_uses_the_time_of_the_event_that_caused_it() {
+    let (mut wm, key_handler) = start_wm();
+    let clients = map_clients(&mut wm, &key_handler, 2);
+    set_protocols(&wm, clients[1], &[wm.atoms.WM_TAKE_FOCUS]);
+    wm.conn.take_requests();
+
+    wm.conn.push_event(EnterNotifyEvent {
+        response_type: ENTER_NOTIFY_EVENT, root: ROOT, event: wm.window_map[&clients[0]], mode: NotifyMode::NORMAL,
+        time: 4242, ..Default::default()
+    });
+    wm.handle_events(&key_handler, &[]).unwrap();
+    let requests = wm.conn.take_requests();
+    assert_eq!(input_focus_requests(&requests), vec![(clients[0], 4242)]);
+    assert!(protocol_messages(&wm, &requests, clients[0]).is_empty());
+
+    wm.conn.push_event(EnterNotifyEvent {
+        response_type: ENTER_NOTIFY_EVENT, root: ROOT, event: wm.window_map[&clients[1]], mode: NotifyMode::NORMAL,
+        time: 4343, ..Default::default()
+    });
+    wm.handle_events(&key_handler, &[]).unwrap();
+    let requests = wm.conn.take_requests();
+    assert_eq!(input_focus_requests(&requests), vec![(clients[1], 4343)]);
+    assert_eq!(protocol_messages(&wm, &requests, clients[1]), vec![(wm.atoms.WM_TAKE_FOCUS, 4343)]);
+}
+
+#[test]
+fn clients_without_the_input_hint_only_get_wm_take_focus() {
+    let (mut wm, key_handler) = start_wm();
+    let client = map_clients(&mut wm, &key_handler, 1)[0];
+    // WM_HINTS with only the input hint (flag 1) set, to false.
+    let mut hints = [0u32; 9];
+    hints[0] = 1;
+    let hints: Vec<u8> = hints.iter().flat_map(|v| v.to_ne_bytes()).collect();
+    wm.conn.set_property(client, AtomEnum::WM_HINTS.into(), AtomEnum::WM_HINTS.into(), 32, &hints);
+    set_protocols(&wm, client, &[wm.atoms.WM_TAKE_FOCUS]);
+    wm.conn.take_requests();
+
+    wm.focus_window(Some(client)).unwrap();
+    let requests = wm.conn.take_requests();
+    assert!(input_focus_requests(&requests).is_empty());
+    assert_eq!(protocol_messages(&wm, &requests, client).iter().map(|m| m.0).collect::<Vec<_>>(), vec![wm.atoms.WM_TAKE_FOCUS]);
+    assert_eq!(wm.focused, Some(client));
+}
+
+#[test]
+fn closing_asks_clients_that_support_it_and_kills_on_the_second_try() {
+    let (mut wm, key_handler) = start_wm();
+    let client = map_clients(&mut wm, &key_handler, 1)[0];
+    set_protocols(&wm, client, &[wm.atoms.WM_DELETE_WINDOW]);
+    wm.focus_window(Some(client)).unwrap();
+    wm.conn.take_requests();
+
+    wm.kill_focused().unwrap();
+    let requests = wm.conn.take_requests();
+    assert_eq!(protocol_messages(&wm, &requests, client).iter().map(|m| m.0).collect::<Vec<_>>(), vec![wm.atoms.WM_DELETE_WINDOW]);
+    assert!(killed_clients(&requests).is_empty());
+    assert!(wm.window_map.contains_key(&client));
+
+    wm.kill_focused().unwrap();
+    let requests = wm.conn.take_requests();
+    assert!(protocol_messages(&wm, &requests, client).is_empty());
+    assert_eq!(killed_clients(&requests), vec![client]);
+    // the client stays managed until the server tells that it is gone.
+    assert!(wm.window_map.contains_key(&client));
+
+    wm.conn.push_event(DestroyNotifyEvent { response_type: DESTROY_NOTIFY_EVENT, sequence: 0, event: client, window: client });
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert!(!wm.window_map.contains_key(&client) && !wm.pending_kills.contains_key(&client));
+}
+
+#[test]
+fn clients_that_ignore_the_close_request_are_killed_after_the_timeout() {
+    let (mut wm, key_handler) = start_wm();
+    let clients = map_clients(&mut wm, &key_handler, 2);
+    for client in &clients {
+        set_protocols(&wm, *client, &[wm.atoms.WM_DELETE_WINDOW]);
+    }
+    wm.config.close_timeout_ms = 0;
+    wm.close_window(clients[0]).unwrap();
+    wm.config.close_timeout_ms = 60_000;
+    wm.close_window(clients[1]).unwrap();
+    wm.conn.take_requests();
+
+    wm.kill_unresponsive().unwrap();
+    assert_eq!(killed_clients(&wm.conn.take_requests()), vec![clients[0]]);
+    assert!(wm.window_map.contains_key(&clients[0]));
+
+    wm.conn.push_event(UnmapNotifyEvent { response_type: UNMAP_NOTIFY_EVENT, sequence: 0, event: clients[0], window: clients[0], from_configure: false });
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert!(!wm.window_map.contains_key(&clients[0]));
+    assert!(wm.window_map.contains_key(&clients[1]) && wm.pending_kills.contains_key(&clients[1]));
+}
+
+#[test]
+fn clients_without_wm_delete_window_are_killed_right_away() {
+    let (mut wm, key_handler) = start_wm();
+    let client = map_clients(&mut wm, &key_handler, 1)[0];
+    wm.conn.take_requests();
+
+    wm.close_window(client).unwrap();
+    let requests = wm.conn.take_requests();
+    assert!(protocol_messages(&wm, &requests, client).is_empty());
+    assert_eq!(killed_clients(&requests), vec![client]);
+    assert!(wm.window_map.contains_key(&client) && !wm.pending_kills.contains_key(&client));
+}
+
+fn stacks(wm: &WM<FakeConnection>) -> Vec<(Vec<Window>, Vec<Window>)> {
+    wm.workspaces.iter().map(|ws| (ws.tiling_win_stack.clone(), ws.floating_win_stack.clone())).collect()
+}
+
+#[test]
+fn reloading_keeps_the_windows_and_regrabs_the_bindings() {
+    let (mut wm, mut key_handler) = start_wm();
+    let clients = map_clients(&mut wm, &key_handler, 2);
+    wm.apply_layout().unwrap();
+    wm.move_to_workspace(clients[1], 1).unwrap();
+    let (window_map, stacks_before) = (wm.window_map.clone(), stacks(&wm));
+    wm.conn.take_requests();
+
+    let mut config = Config::default();
+    config.keybinds.insert("M4+z".to_string(), "workspace 4".parse().unwrap());
+    wm.reload_config(Ok(config), &mut key_handler).unwrap();
+
+    assert_eq!(wm.window_map, window_map);
+    assert_eq!(stacks(&wm), stacks_before);
+    assert_eq!(wm.current_workspace(), 0);
+    let requests = wm.conn.take_requests();
+    let z = wm.conn.keycode("z");
+    for win in [ROOT, clients[0], clients[1]] {
+        assert!(requests.iter().any(|r| matches!(r, Request::UngrabKey(u) if u.grab_window == win && u.key == u8::from(Grab::ANY))));
+        assert!(requests.iter().any(|r| matches!(r, Request::GrabKey(g) if g.grab_window == win && g.key == z)));
+    }
+    for win in clients {
+        assert!(requests.iter().any(|r| matches!(r, Request::UngrabButton(u) if u.grab_window == win)));
+        assert!(requests.iter().any(|r| matches!(r, Request::GrabButton(g) if g.grab_window == win)));
+    }
+
+    wm.conn.key_press(KeyButMask::MOD4, "z");
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.current_workspace(), 3);
+}
+
+#[test]
+fn a_config_that_fails_to_load_leaves_the_previous_one_active() {
+    let (mut wm, mut key_handler) = start_wm_with(Config { close_timeout_ms: 1234, ..Default::default() });
+    let clients = map_clients(&mut wm, &key_handler, 1);
+    wm.conn.take_requests();
+
+    let path = std::env::temp_dir().join(format!("mswm-test-{}-broken.toml", std::process::id()));
+    std::fs::write(&path, "[keybinds]\n\"M4+z\" = \"workspace 4\"\nclose_timeout_ms = \n").unwrap();
+    let loaded = Config::load_from(path.clone(), wm.layouts());
+    std::fs::remove_file(&path).unwrap();
+    assert!(loaded.is_err());
+    wm.reload_config(loaded, &mut key_handler).unwrap();
+
+    assert_eq!(wm.config.close_timeout_ms, 1234);
+    assert!(wm.window_map.contains_key(&clients[0]));
+    assert!(!wm.conn.take_requests().iter().any(|r| matches!(r, Request::UngrabKey(_) | Request::GrabKey(_))));
+    wm.conn.key_press(KeyButMask::MOD4, "z");
+    wm.conn.key_press(KeyButMask::MOD4, "2");
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.current_workspace(), 1);
+}
+
+fn root_property32(wm: &WM<FakeConnection>, atom: Atom) -> Vec<u32> {
+    wm.conn.window(ROOT).unwrap().properties[&atom].2.chunks(4).map(|c| u32::from_ne_bytes(c.try_into().unwrap())).collect()
+}
+
+#[test]
+fn docks_shrink_the_workarea_of_every_desktop() {
+    let (mut wm, key_handler) = start_wm();
+    wm.monitors = vec![
+        Monitor { rect: rect(0, 0, 1000, 800), workspace: 0 },
+        Monitor { rect: rect(1000, 0, 1000, 600), workspace: 1 }
+    ];
+    let bar = wm.config.top_bar.height as u32;
+    let dock = wm.conn.create_client(rect(1000, 0, 1000, 30));
+    wm.conn.set_property(dock, wm.atoms._NET_WM_WINDOW_TYPE, AtomEnum::ATOM.into(), 32, &wm.atoms._NET_WM_WINDOW_TYPE_DOCK.to_ne_bytes());
+    let strut: Vec<u8> = [0u32, 0, 30, 0, 0, 0, 0, 0, 1000, 1999, 0, 0].iter().flat_map(|v| v.to_ne_bytes()).collect();
+    wm.conn.set_property(dock, wm.atoms._NET_WM_STRUT_PARTIAL, AtomEnum::CARDINAL.into(), 32, &strut);
+    wm.conn.map_request(dock);
+    wm.handle_events(&key_handler, &[]).unwrap();
+
+    // EWMH has a single work area per desktop, it spans both monitors.
+    let workarea = root_property32(&wm, wm.atoms._NET_WORKAREA);
+    assert_eq!(workarea.len(), 4 * wm.workspaces.len());
+    assert!(workarea.chunks(4).all(|area| area == [0, 30 + bar, 2000, 770 - bar]));
+    assert_eq!(wm.layout_area(1), rect(1000, 30 + bar as i16, 1000, 570 - bar as u16));
+    assert_eq!(wm.layout_area(0), rect(0, bar as i16, 1000, 800 - bar as u16));
+
+    wm.conn.push_event(DestroyNotifyEvent { response_type: DESTROY_NOTIFY_EVENT, sequence: 0, event: dock, window: dock });
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert!(root_property32(&wm, wm.atoms._NET_WORKAREA).chunks(4).all(|area| area == [0, bar, 2000, 800 - bar]));
+}
+
+fn client_message(wm: &WM<FakeConnection>, win: Window, type_: Atom, data: [u32; 5]) {
+    wm.conn.push_event(ClientMessageEvent::new(32, win, type_, data));
+}
+
+#[test]
+fn pagers_can_activate_windows_on_other_desktops() {
+    let (mut wm, key_handler) = start_wm();
+    let clients = map_clients(&mut wm, &key_handler, 2);
+    wm.move_to_workspace(clients[1], 2).unwrap();
+    wm.focus_window(Some(clients[0])).unwrap();
+
+    // source indication 2 is a pager, followed by the time of the request.
+    client_message(&wm, clients[1], wm.atoms._NET_ACTIVE_WINDOW, [2, 0, 0, 0, 0]);
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.current_workspace(), 2);
+    assert_eq!(wm.focused, Some(clients[1]));
+    assert_eq!(root_property32(&wm, wm.atoms._NET_ACTIVE_WINDOW), vec![clients[1]]);
+    assert_eq!(root_property32(&wm, wm.atoms._NET_CURRENT_DESKTOP), vec![2]);
+    assert!(wm.conn.window(wm.window_map[&clients[1]]).unwrap().mapped);
+    assert!(!wm.conn.window(wm.window_map[&clients[0]]).unwrap().mapped);
+}
+
+#[test]
+fn pagers_can_switch_desktops() {
+    let (mut wm, key_handler) = start_wm();
+    let client = map_clients(&mut wm, &key_handler, 1)[0];
+
+    client_message(&wm, ROOT, wm.atoms._NET_CURRENT_DESKTOP, [3, 0, 0, 0, 0]);
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.current_workspace(), 3);
+    assert_eq!(root_property32(&wm, wm.atoms._NET_CURRENT_DESKTOP), vec![3]);
+    assert!(!wm.conn.window(wm.window_map[&client]).unwrap().mapped);
+
+    // desktops that do not exist are ignored.
+    client_message(&wm, ROOT, wm.atoms._NET_CURRENT_DESKTOP, [99, 0, 0, 0, 0]);
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.current_workspace(), 3);
+    assert_eq!(root_property32(&wm, wm.atoms._NET_CURRENT_DESKTOP), vec![3]);
+}
+
+#[test]
+fn pagers_can_close_windows() {
+    let (mut wm, key_handler) = start_wm();
+    let clients = map_clients(&mut wm, &key_handler, 2);
+    set_protocols(&wm, clients[0], &[wm.atoms.WM_DELETE_WINDOW]);
+    wm.conn.take_requests();
+
+    client_message(&wm, clients[0], wm.atoms._NET_CLOSE_WINDOW, [0, 2, 0, 0, 0]);
+    client_message(&wm, clients[1], wm.atoms._NET_CLOSE_WINDOW, [0, 2, 0, 0, 0]);
+    // windows that are not managed are left alone.
+    client_message(&wm, ROOT, wm.atoms._NET_CLOSE_WINDOW, [0, 2, 0, 0, 0]);
+    wm.handle_events(&key_handler, &[]).unwrap();
+    let requests = wm.conn.take_requests();
+    assert_eq!(protocol_messages(&wm, &requests, clients[0]).iter().map(|m| m.0).collect::<Vec<_>>(), vec![wm.atoms.WM_DELETE_WINDOW]);
+    assert_eq!(killed_clients(&requests), vec![clients[1]]);
+
+    wm.conn.push_event(UnmapNotifyEvent { response_type: UNMAP_NOTIFY_EVENT, sequence: 0, event: clients[0], window: clients[0], from_configure: false });
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert!(!wm.window_map.contains_key(&clients[0]));
+    assert_eq!(root_property32(&wm, wm.atoms._NET_CLIENT_LIST), vec![clients[1]]);
+}
+
+#[test]
+fn huge_timeouts_do_not_overflow() {
+    let mut config = Config::default();
+    config.keybinds.insert("M4+a x".to_string(), "workspace 3".parse().unwrap());
+    config.chord_timeout_ms = u64::MAX;
+    config.close_timeout_ms = u64::MAX;
+    let (mut wm, key_handler) = start_wm_with(config);
+    let client = map_clients(&mut wm, &key_handler, 1)[0];
+    set_protocols(&wm, client, &[wm.atoms.WM_DELETE_WINDOW]);
+    wm.close_window(client).unwrap();
+    wm.conn.key_press(KeyButMask::MOD4, "a");
+    wm.handle_events(&key_handler, &[]).unwrap();
+    assert_eq!(wm.key_chord.as_deref(), Some("M4+a"));
+    assert!(wm.pending_kills.contains_key(&client));
+
+    // the deadlines are weeks away, more than poll can wait for in one go.
+    let (wake, woken) = std::os::unix::net::UnixStream::pair().unwrap();
+    std::io::Write::write_all(&mut &wake, b"x").unwrap();
+    wm.wait_for_activity(&[woken.as_raw_fd()], key_handler.chord_deadline()).unwrap();
+}
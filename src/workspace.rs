@@ -0,0 +1,46 @@
+use x11rb::protocol::xproto::Window;
+
+use crate::config::LayoutConfig;
+
+pub struct Workspace {
+    pub tiling_win_stack: Vec<Window>,
+    pub floating_win_stack: Vec<Window>,
+    pub focus_history: Vec<Window>,
+    pub layout: String,
+    pub master_ratio: f32,
+    pub master_count: usize
+}
+
+impl Workspace {
+    pub fn new(config: &LayoutConfig) -> Self {
+        Self {
+            tiling_win_stack: Vec::new(),
+            floating_win_stack: Vec::new(),
+            focus_history: Vec::new(),
+            layout: config.default.clone(),
+            master_ratio: config.master_ratio,
+            master_count: config.master_count
+        }
+    }
+
+    pub fn contains(&self, frame: Window) -> bool {
+        self.tiling_win_stack.contains(&frame) || self.floating_win_stack.contains(&frame)
+    }
+
+    pub fn frames(&self) -> impl Iterator<Item = &Window> {
+        self.tiling_win_stack.iter().chain(self.floating_win_stack.iter())
+    }
+
+    // removes the frame from the stacks and returns whether it was tiled.
+    pub fn remove(&mut self, frame: Window) -> Option<bool> {
+        if let Some(index) = self.tiling_win_stack.iter().position(|w| *w == frame) {
+            self.tiling_win_stack.remove(index);
+            Some(true)
+        } else if let Some(index) = self.floating_win_stack.iter().position(|w| *w == frame) {
+            self.floating_win_stack.remove(index);
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,313 @@
+// runs mswm against a virtual X server and drives it like a user would, with client windows created
+// through a separate connection and input simulated via XTEST. the tests need Xvfb, so they only
+// run with cargo test -- --ignored.
+
+use std::io::{ErrorKind, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::*;
+use x11rb::protocol::xtest::ConnectionExt as _;
+use x11rb::rust_connection::RustConnection;
+
+use mswm::config::Config;
+use mswm::keybind::{init_keymap, KeyBindHandler};
+use mswm::layout::LayoutRegistry;
+use mswm::wm::WM;
+
+const SCREEN: (u16, u16) = (1024, 768);
+const TIMEOUT: Duration = Duration::from_secs(5);
+// displays tried before giving up, each one may have been taken by another server.
+const MAX_ATTEMPTS: u32 = 16;
+
+const XK_SUPER_L: Keysym = 0xffeb;
+const XK_G: Keysym = 0x67;
+
+// tests run in parallel, so each one gets a display of its own.
+static NEXT_DISPLAY: AtomicU32 = AtomicU32::new(0);
+
+struct Xvfb {
+    process: Child,
+    display: String
+}
+
+impl Xvfb {
+    fn start() -> Self {
+        let base = 100 + std::process::id() % 400 * 16;
+        for _ in 0..MAX_ATTEMPTS {
+            let n = base + NEXT_DISPLAY.fetch_add(1, Ordering::SeqCst);
+            let lock = format!("/tmp/.X{}-lock", n);
+            if Path::new(&lock).exists() {
+                continue;
+            }
+            let display = format!(":{}", n);
+            let process = Command::new("Xvfb")
+                .args([&display, "-screen", "0", &format!("{}x{}x24", SCREEN.0, SCREEN.1), "-nolisten", "tcp"])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+            let mut xvfb = match process {
+                Ok(process) => Self { process, display },
+                Err(err) if err.kind() == ErrorKind::NotFound => panic!("Xvfb is not installed"),
+                Err(err) => panic!("failed to start Xvfb: {}", err)
+            };
+            let start = Instant::now();
+            while start.elapsed() < TIMEOUT {
+                if let Some(status) = xvfb.process.try_wait().unwrap() {
+                    // the display was taken by someone else in the meantime.
+                    if Path::new(&lock).exists() {
+                        break;
+                    }
+                    panic!("Xvfb exited with {}", status);
+                }
+                if x11rb::connect(Some(&xvfb.display)).is_ok() {
+                    return xvfb;
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+        }
+        panic!("Xvfb did not start on any of {} displays", MAX_ATTEMPTS);
+    }
+}
+
+impl Drop for Xvfb {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+// an X server with a client connection, and mswm running in a thread once started.
+struct Session {
+    conn: RustConnection,
+    root: Window,
+    wm: Option<(JoinHandle<()>, Arc<AtomicBool>, UnixStream)>,
+    // dropped last, the WM has to exit before its server goes away.
+    xvfb: Xvfb
+}
+
+impl Session {
+    fn new() -> Self {
+        let xvfb = Xvfb::start();
+        let (conn, screen_num) = x11rb::connect(Some(&xvfb.display)).unwrap();
+        let root = conn.setup().roots[screen_num].root;
+        Self { conn, root, wm: None, xvfb }
+    }
+
+    fn start_wm(&mut self) {
+        let display = self.xvfb.display.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let (wake, wake_wm) = UnixStream::pair().unwrap();
+        let stop_wm = stop.clone();
+        let handle = thread::spawn(move || {
+            let (conn, screen_num) = x11rb::connect(Some(&display)).unwrap();
+            init_keymap(&conn).unwrap();
+            let config = Config::default();
+            let key_handler = KeyBindHandler::from_config(&config).unwrap();
+            let mut wm = WM::create_wm(conn, screen_num, config, LayoutRegistry::default(), &key_handler).unwrap();
+            wm.scan(&key_handler).unwrap();
+            while !stop_wm.load(Ordering::SeqCst) {
+                wm.handle_events(&key_handler, &[wake_wm.as_raw_fd()]).unwrap();
+            }
+        });
+        self.wm = Some((handle, stop, wake));
+        // the WM is ready once it advertises itself.
+        let check = self.atom("_NET_SUPPORTING_WM_CHECK");
+        self.wait_until("the WM to start", || !self.property(self.root, check).is_empty());
+    }
+
+    fn atom(&self, name: &str) -> Atom {
+        self.conn.intern_atom(false, name.as_bytes()).unwrap().reply().unwrap().atom
+    }
+
+    fn property(&self, win: Window, property: Atom) -> Vec<u32> {
+        let reply = self.conn.get_property(false, win, property, AtomEnum::ANY, 0, 1024).unwrap().reply().unwrap();
+        reply.value32().map(|v| v.collect()).unwrap_or_default()
+    }
+
+    fn wait_until(&self, what: &str, mut condition: impl FnMut() -> bool) {
+        let start = Instant::now();
+        while !condition() {
+            assert!(start.elapsed() < TIMEOUT, "timed out waiting for {}", what);
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn create_window(&self, x: i16, y: i16, width: u16, height: u16) -> Window {
+        let win = self.conn.generate_id().unwrap();
+        self.conn.create_window(
+            x11rb::COPY_DEPTH_FROM_PARENT, win, self.root, x, y, width, height, 0,
+            WindowClass::INPUT_OUTPUT, x11rb::COPY_FROM_PARENT, &CreateWindowAux::new()
+        ).unwrap();
+        win
+    }
+
+    fn map(&self, win: Window) {
+        self.conn.map_window(win).unwrap();
+        self.conn.flush().unwrap();
+    }
+
+    fn parent(&self, win: Window) -> Option<Window> {
+        self.conn.query_tree(win).ok()?.reply().ok().map(|tree| tree.parent)
+    }
+
+    // the frame mswm put the client into.
+    fn wait_for_frame(&self, win: Window) -> Window {
+        self.wait_until("the window to be reparented", || self.parent(win).is_some_and(|p| p != self.root));
+        self.parent(win).unwrap()
+    }
+
+    fn geometry(&self, win: Window) -> Rectangle {
+        let geom = self.conn.get_geometry(win).unwrap().reply().unwrap();
+        Rectangle { x: geom.x, y: geom.y, width: geom.width, height: geom.height }
+    }
+
+    fn keycode(&self, keysym: Keysym) -> Keycode {
+        let setup = self.conn.setup();
+        let mapping = self.conn.get_keyboard_mapping(setup.min_keycode, setup.max_keycode - setup.min_keycode + 1)
+            .unwrap().reply().unwrap();
+        let per_keycode = mapping.keysyms_per_keycode as usize;
+        let index = mapping.keysyms.iter().position(|k| *k == keysym)
+            .unwrap_or_else(|| panic!("no keycode for keysym {:#x}", keysym));
+        setup.min_keycode + (index / per_keycode) as u8
+    }
+
+    // presses the keys in order and releases them in reverse.
+    fn press_keys(&self, keysyms: &[Keysym]) {
+        let keycodes: Vec<Keycode> = keysyms.iter().map(|k| self.keycode(*k)).collect();
+        for keycode in &keycodes {
+            self.conn.xtest_fake_input(KEY_PRESS_EVENT, *keycode, x11rb::CURRENT_TIME, self.root, 0, 0, 0).unwrap();
+        }
+        for keycode in keycodes.iter().rev() {
+            self.conn.xtest_fake_input(KEY_RELEASE_EVENT, *keycode, x11rb::CURRENT_TIME, self.root, 0, 0, 0).unwrap();
+        }
+        self.conn.flush().unwrap();
+    }
+
+    fn move_pointer(&self, x: i16, y: i16) {
+        self.conn.xtest_fake_input(MOTION_NOTIFY_EVENT, 0, x11rb::CURRENT_TIME, self.root, x, y, 0).unwrap();
+        self.conn.flush().unwrap();
+    }
+
+    fn input_focus(&self) -> Window {
+        self.conn.get_input_focus().unwrap().reply().unwrap().focus
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Some((handle, stop, mut wake)) = self.wm.take() {
+            stop.store(true, Ordering::SeqCst);
+            let _ = wake.write_all(&[0]);
+            let result = handle.join();
+            if !thread::panicking() {
+                result.expect("the WM panicked");
+            }
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn new_windows_are_reparented_into_frames() {
+    let mut s = Session::new();
+    s.start_wm();
+    let win = s.create_window(10, 30, 300, 200);
+    s.map(win);
+
+    let frame = s.wait_for_frame(win);
+    assert_eq!(s.parent(frame), Some(s.root));
+    // new windows float where they asked to be, below the top bar.
+    assert_eq!(s.geometry(frame), Rectangle { x: 10, y: 50, width: 300, height: 200 });
+    assert_eq!(s.geometry(win), Rectangle { x: 0, y: 0, width: 300, height: 200 });
+    assert_eq!(s.conn.get_window_attributes(frame).unwrap().reply().unwrap().map_state, MapState::VIEWABLE);
+    assert_eq!(s.property(s.root, s.atom("_NET_CLIENT_LIST")), vec![win]);
+    assert_eq!(s.property(win, s.atom("_NET_WM_DESKTOP")), vec![0]);
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn windows_mapped_before_startup_stay_managed() {
+    let mut s = Session::new();
+    let win = s.create_window(0, 0, 300, 200);
+    s.map(win);
+    s.conn.get_input_focus().unwrap().reply().unwrap();
+    s.start_wm();
+
+    // reparenting a mapped window unmaps it, which must not be taken for the client withdrawing.
+    let frame = s.wait_for_frame(win);
+    // events are handled in order, so the unmap has been seen once the next window is managed.
+    let other = s.create_window(0, 0, 300, 200);
+    s.map(other);
+    s.wait_for_frame(other);
+    assert_eq!(s.parent(win), Some(frame));
+    assert_eq!(s.conn.get_window_attributes(win).unwrap().reply().unwrap().map_state, MapState::VIEWABLE);
+    assert_eq!(s.property(s.root, s.atom("_NET_CLIENT_LIST")), vec![win, other]);
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn destroyed_windows_are_unmanaged() {
+    let mut s = Session::new();
+    s.start_wm();
+    let win = s.create_window(0, 0, 300, 200);
+    s.map(win);
+    let frame = s.wait_for_frame(win);
+
+    s.conn.destroy_window(win).unwrap();
+    s.conn.flush().unwrap();
+
+    let client_list = s.atom("_NET_CLIENT_LIST");
+    s.wait_until("the window to be unmanaged", || s.property(s.root, client_list).is_empty());
+    s.wait_until("the frame to be destroyed", || s.conn.get_geometry(frame).unwrap().reply().is_err());
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn tree_layout_splits_the_screen() {
+    let mut s = Session::new();
+    s.start_wm();
+    let first = s.create_window(0, 0, 300, 200);
+    s.map(first);
+    let first_frame = s.wait_for_frame(first);
+    let second = s.create_window(0, 0, 300, 200);
+    s.map(second);
+    let second_frame = s.wait_for_frame(second);
+
+    s.press_keys(&[XK_SUPER_L, XK_G]);
+
+    let (half, height) = (SCREEN.0 / 2, SCREEN.1 - 20);
+    s.wait_until("the windows to be tiled", || s.geometry(second_frame).x == half as i16);
+    assert_eq!(s.geometry(first_frame), Rectangle { x: 0, y: 20, width: half, height });
+    assert_eq!(s.geometry(second_frame), Rectangle { x: half as i16, y: 20, width: half, height });
+    assert_eq!(s.geometry(first), Rectangle { x: 0, y: 0, width: half, height });
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn focus_follows_the_pointer() {
+    let mut s = Session::new();
+    s.start_wm();
+    let windows: Vec<Window> = (0..2).map(|_| {
+        let win = s.create_window(0, 0, 300, 200);
+        s.map(win);
+        s.wait_for_frame(win);
+        win
+    }).collect();
+    s.press_keys(&[XK_SUPER_L, XK_G]);
+    let frame = s.parent(windows[1]).unwrap();
+    s.wait_until("the windows to be tiled", || s.geometry(frame).x > 0);
+
+    let active = s.atom("_NET_ACTIVE_WINDOW");
+    for (win, x) in windows.iter().zip([100, SCREEN.0 as i16 - 100]) {
+        s.move_pointer(x, 300);
+        s.wait_until("the window to be focused", || s.input_focus() == *win);
+        assert_eq!(s.property(s.root, active), vec![*win]);
+    }
+}